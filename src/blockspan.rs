@@ -1,14 +1,86 @@
-use crate::{timespan::ToDateTimeUtc, TimeSpan};
+use crate::{
+    cache::{Bound, Cache},
+    timespan::ToDateTimeUtc,
+    TimeSpan,
+};
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use sqlx::PgPool;
 use std::result::Result as StdResult;
 
-#[derive(Debug, sqlx::FromRow, Serialize)]
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
 pub struct BlockSpan {
     pub low: i64,
     pub high: i64,
 }
 
+/// Errors resolving a block span. The messages are meant to be shown to the
+/// user as is and suggest how to fix the request.
+#[derive(Debug, thiserror::Error)]
+pub enum BlockSpanError {
+    #[error(
+        "the timespan ends at {high} which is before the first block at {genesis}, \
+         use a later date"
+    )]
+    BeforeGenesis {
+        high: DateTime<Utc>,
+        genesis: DateTime<Utc>,
+    },
+    #[error(
+        "the timespan starts at {low} which is after the latest block at {tip}, \
+         use an earlier date"
+    )]
+    AfterTip {
+        low: DateTime<Utc>,
+        tip: DateTime<Utc>,
+    },
+    #[error(
+        "the timespan starts at {low} but the latest ingested block {height} is from {tip}, \
+         the etl is lagging behind; check that it is running or try again later"
+    )]
+    EtlLagging {
+        low: DateTime<Utc>,
+        height: i64,
+        tip: DateTime<Utc>,
+    },
+    #[error("there are no blocks between {low} and {high}, use a wider timespan")]
+    Empty {
+        low: DateTime<Utc>,
+        high: DateTime<Utc>,
+    },
+    #[error("block {height} does not exist, known blocks range from {genesis} to {tip}")]
+    UnknownBlock { height: i64, genesis: i64, tip: i64 },
+    #[error("the blockchain has no blocks yet, check that the etl has been started")]
+    NoBlocks,
+    #[error("the timespan is outside of the supported range of dates")]
+    OutOfRange,
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl BlockSpanError {
+    /// Whether the error is for a timespan without any blocks, as opposed to
+    /// a failure to look them up
+    pub fn is_no_blocks(&self) -> bool {
+        matches!(
+            self,
+            Self::BeforeGenesis { .. }
+                | Self::AfterTip { .. }
+                | Self::EtlLagging { .. }
+                | Self::Empty { .. }
+        )
+    }
+}
+
+/// The first and the latest block that have been ingested
+#[derive(Debug, sqlx::FromRow, Serialize)]
+pub struct ChainBounds {
+    pub genesis_height: i64,
+    pub genesis_timestamp: DateTime<Utc>,
+    pub tip_height: i64,
+    pub tip_timestamp: DateTime<Utc>,
+}
+
 const BLOCKSPAN_QUERY: &str = r#"
     with max as (
         select height from blocks where timestamp <= $1 order by timestamp desc limit 1
@@ -16,7 +88,25 @@ const BLOCKSPAN_QUERY: &str = r#"
     min as (
         select height from blocks where timestamp >= $2 order by timestamp limit 1
     )
-    select (select height from max) as high, (select height from min) as low            
+    select
+        (select height from max) as high,
+        (select height from min) as low,
+        exists(select 1 from blocks where timestamp > $1) as high_final
+"#;
+
+const BLOCK_TIMESTAMP_QUERY: &str = r#"
+    select timestamp from blocks where height = $1
+"#;
+
+const CHAIN_BOUNDS_QUERY: &str = r#"
+    select
+        g.height as genesis_height,
+        g.timestamp as genesis_timestamp,
+        t.height as tip_height,
+        t.timestamp as tip_timestamp
+    from
+        (select height, timestamp from blocks order by height limit 1) g,
+        (select height, timestamp from blocks order by height desc limit 1) t
 "#;
 
 impl BlockSpan {
@@ -24,8 +114,8 @@ impl BlockSpan {
         pool: &PgPool,
         date: S,
         days: i64,
-    ) -> StdResult<Self, sqlx::Error> {
-        let timespan = TimeSpan::new(date, days);
+    ) -> StdResult<Self, BlockSpanError> {
+        let timespan = TimeSpan::new(date, days).map_err(|_| BlockSpanError::OutOfRange)?;
         Self::for_timespan(pool, &timespan).await
     }
 
@@ -33,17 +123,129 @@ impl BlockSpan {
         pool: &PgPool,
         start: S,
         end: E,
-    ) -> StdResult<Self, sqlx::Error> {
+    ) -> StdResult<Self, BlockSpanError> {
         let timespan = TimeSpan::for_date_range(start, end);
         Self::for_timespan(pool, &timespan).await
     }
 
-    pub async fn for_timespan(pool: &PgPool, timespan: &TimeSpan) -> StdResult<Self, sqlx::Error> {
-        let span: BlockSpan = sqlx::query_as(BLOCKSPAN_QUERY)
-            .bind(timespan.high)
-            .bind(timespan.low)
-            .fetch_one(pool)
-            .await?;
-        Ok(span)
+    pub async fn for_timespan(
+        pool: &PgPool,
+        timespan: &TimeSpan,
+    ) -> StdResult<Self, BlockSpanError> {
+        let (low, high) = lookup_heights(pool, timespan).await?;
+        match (low, high) {
+            (Some(low), Some(high)) if low <= high => Ok(Self { low, high }),
+            (Some(_), Some(_)) => Err(BlockSpanError::Empty {
+                low: timespan.low,
+                high: timespan.high,
+            }),
+            _ => Err(ChainBounds::fetch(pool).await?.outside(timespan)),
+        }
+    }
+
+    /// The timespan from the timestamp of the low block to the timestamp of
+    /// the high block.
+    pub async fn timespan(&self, pool: &PgPool) -> StdResult<TimeSpan, BlockSpanError> {
+        let low = block_timestamp(pool, self.low).await?;
+        let high = block_timestamp(pool, self.high).await?;
+        Ok(TimeSpan::for_date_range(low, high))
+    }
+
+    /// Narrows this block span to the blocks that are also in the given one
+    pub fn clip(&self, other: &BlockSpan) -> Self {
+        Self {
+            low: self.low.max(other.low),
+            high: self.high.min(other.high),
+        }
+    }
+}
+
+impl ChainBounds {
+    pub async fn fetch(pool: &PgPool) -> StdResult<Self, BlockSpanError> {
+        sqlx::query_as(CHAIN_BOUNDS_QUERY)
+            .fetch_optional(pool)
+            .await?
+            .ok_or(BlockSpanError::NoBlocks)
+    }
+
+    /// The error for a timespan that has no blocks because it is not within
+    /// these bounds
+    fn outside(&self, timespan: &TimeSpan) -> BlockSpanError {
+        if timespan.high < self.genesis_timestamp {
+            BlockSpanError::BeforeGenesis {
+                high: timespan.high,
+                genesis: self.genesis_timestamp,
+            }
+        } else if timespan.low > self.tip_timestamp && timespan.low < Utc::now() {
+            BlockSpanError::EtlLagging {
+                low: timespan.low,
+                height: self.tip_height,
+                tip: self.tip_timestamp,
+            }
+        } else if timespan.low > self.tip_timestamp {
+            BlockSpanError::AfterTip {
+                low: timespan.low,
+                tip: self.tip_timestamp,
+            }
+        } else {
+            BlockSpanError::Empty {
+                low: timespan.low,
+                high: timespan.high,
+            }
+        }
+    }
+}
+
+/// Looks up the first block at or after the start and the last block at or
+/// before the end of the given timespan, using the cache where possible.
+async fn lookup_heights(
+    pool: &PgPool,
+    timespan: &TimeSpan,
+) -> StdResult<(Option<i64>, Option<i64>), sqlx::Error> {
+    let cache = Cache::global();
+    if let Some(cache) = cache {
+        let low = cache.get(Bound::Low, timespan.low).await?;
+        let high = cache.get(Bound::High, timespan.high).await?;
+        if low.is_some() && high.is_some() {
+            return Ok((low, high));
+        }
+    }
+    let (high, low, high_final): (Option<i64>, Option<i64>, bool) = sqlx::query_as(BLOCKSPAN_QUERY)
+        .bind(timespan.high)
+        .bind(timespan.low)
+        .fetch_one(pool)
+        .await?;
+    if let Some(cache) = cache {
+        // Blocks are ingested in order, so the first block after a timestamp
+        // can not change once it exists, and the last block before one can not
+        // change once a later block exists.
+        if let Some(low) = low {
+            cache.put(Bound::Low, timespan.low, low).await?;
+        }
+        if let Some(high) = high.filter(|_| high_final) {
+            cache.put(Bound::High, timespan.high, high).await?;
+        }
+    }
+    Ok((low, high))
+}
+
+pub async fn block_timestamp(
+    pool: &PgPool,
+    height: i64,
+) -> StdResult<DateTime<Utc>, BlockSpanError> {
+    let timestamp: Option<(DateTime<Utc>,)> = sqlx::query_as(BLOCK_TIMESTAMP_QUERY)
+        .bind(height)
+        .fetch_optional(pool)
+        .await?;
+    match timestamp {
+        Some((timestamp,)) => Ok(timestamp),
+        None => {
+            let bounds = ChainBounds::fetch(pool).await?;
+            Err(BlockSpanError::UnknownBlock {
+                height,
+                genesis: bounds.genesis_height,
+                tip: bounds.tip_height,
+            })
+        }
     }
 }
@@ -0,0 +1,126 @@
+//! A local cache of the block heights that timestamps resolve to.
+//!
+//! Resolving a timespan to a [`BlockSpan`](crate::BlockSpan) looks up the
+//! first block at or after its start and the last block at or before its end.
+//! Once the etl has ingested blocks past a timestamp the result of that lookup
+//! can no longer change, so it is stored in a small SQLite file and reused by
+//! later runs. Lookups that are not final yet always go to the database.
+use crate::Result;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use std::{io, path::Path, result::Result as StdResult, sync::OnceLock};
+
+static CACHE: OnceLock<Cache> = OnceLock::new();
+
+const CREATE_TABLE: &str = r#"
+    create table if not exists heights (
+        database text not null,
+        bound text not null,
+        timestamp integer not null,
+        height integer not null,
+        primary key (database, bound, timestamp)
+    )
+"#;
+
+const GET_HEIGHT: &str = r#"
+    select height from heights where database = $1 and bound = $2 and timestamp = $3
+"#;
+
+const PUT_HEIGHT: &str = r#"
+    insert or replace into heights (database, bound, timestamp, height) values ($1, $2, $3, $4)
+"#;
+
+/// Which block a timestamp resolves to
+#[derive(Debug, Clone, Copy)]
+pub enum Bound {
+    /// The first block at or after the timestamp
+    Low,
+    /// The last block at or before the timestamp
+    High,
+}
+
+impl Bound {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::High => "high",
+        }
+    }
+}
+
+pub struct Cache {
+    pool: SqlitePool,
+    /// Identifies the database the cached heights were looked up in
+    database: String,
+}
+
+impl Cache {
+    /// Opens or creates the cache file at the given path for lookups in the
+    /// given database.
+    pub async fn open(path: &Path, database: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        sqlx::query(CREATE_TABLE).execute(&pool).await?;
+        Ok(Self {
+            pool,
+            database: database.to_string(),
+        })
+    }
+
+    /// Makes this the cache used by all block span lookups
+    pub fn install(self) {
+        let _ = CACHE.set(self);
+    }
+
+    /// The installed cache, if any
+    pub fn global() -> Option<&'static Self> {
+        CACHE.get()
+    }
+
+    /// Removes the cache file at the given path, along with the write-ahead
+    /// log and shared memory files sqlite keeps next to it
+    pub fn clear(path: &Path) -> Result {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.as_os_str().to_owned();
+            file.push(suffix);
+            match std::fs::remove_file(&file) {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn get(
+        &self,
+        bound: Bound,
+        timestamp: DateTime<Utc>,
+    ) -> StdResult<Option<i64>, sqlx::Error> {
+        let height: Option<(i64,)> = sqlx::query_as(GET_HEIGHT)
+            .bind(&self.database)
+            .bind(bound.as_str())
+            .bind(timestamp.timestamp_micros())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(height.map(|(height,)| height))
+    }
+
+    pub async fn put(
+        &self,
+        bound: Bound,
+        timestamp: DateTime<Utc>,
+        height: i64,
+    ) -> StdResult<(), sqlx::Error> {
+        sqlx::query(PUT_HEIGHT)
+            .bind(&self.database)
+            .bind(bound.as_str())
+            .bind(timestamp.timestamp_micros())
+            .bind(height)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
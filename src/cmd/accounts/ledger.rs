@@ -0,0 +1,227 @@
+use crate::{
+    cmd::{
+        parquet::{timestamp_field, ArrowSchema},
+        Format, Opts, RangeArgs,
+    },
+    Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgPool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Generates output in any --format with every balance affecting event of a
+/// given wallet: payments, rewards, fees, burns, stakes, unstakes and
+/// transfers. Every entry has a signed amount of a single token and the running
+/// balance of that token, starting from the balance of the wallet before the
+/// timespan. Fees are in DC. Burned HNT is credited to the payee as DC at the
+/// oracle price of the burn's block, at 0.00001 USD per DC.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+pub struct Cmd {
+    /// The wallet address to list the ledger for
+    account: String,
+
+    #[structopt(flatten)]
+    range: RangeArgs,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct Entry {
+    block: i64,
+    timestamp: DateTime<Utc>,
+    transaction_hash: String,
+    #[serde(rename = "type")]
+    #[sqlx(rename = "type")]
+    transaction_type: String,
+    kind: String,
+    token: String,
+    counterparty: Option<String>,
+    amount: f64,
+    balance: f64,
+}
+
+impl ArrowSchema for Entry {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("block", DataType::Int64, false),
+            timestamp_field("timestamp", false),
+            Field::new("transaction_hash", DataType::Utf8, false),
+            Field::new("type", DataType::Utf8, false),
+            Field::new("kind", DataType::Utf8, false),
+            Field::new("token", DataType::Utf8, false),
+            Field::new("counterparty", DataType::Utf8, true),
+            Field::new("amount", DataType::Float64, false),
+            Field::new("balance", DataType::Float64, false),
+        ])
+    }
+}
+
+const LEDGER_QUERY: &str = r#"
+    with txns as (
+        select distinct t.block, t.hash, t.type, t.fields
+        from transaction_actors a
+        inner join transactions t on t.hash = a.transaction_hash
+        where a.block between $1 and $2
+            and a.actor = $3
+            and t.type in (
+                'payment_v1',
+                'payment_v2',
+                'security_exchange_v1',
+                'token_burn_v1',
+                'stake_validator_v1',
+                'unstake_validator_v1',
+                'transfer_validator_stake_v1',
+                'transfer_hotspot_v1'
+            )
+    ),
+    entries as (
+        select t.block, t.hash, t.type, 'payment' as kind, 'hnt' as token,
+            t.fields->>'payee' as counterparty, -(t.fields->>'amount')::bigint as amount
+        from txns t
+        where t.type = 'payment_v1' and t.fields->>'payer' = $3
+        union all
+        select t.block, t.hash, t.type, 'payment', 'hnt', t.fields->>'payer', (t.fields->>'amount')::bigint
+        from txns t
+        where t.type = 'payment_v1' and t.fields->>'payee' = $3
+        union all
+        select t.block, t.hash, t.type, 'payment', coalesce(p->>'token_type', 'hnt'), p->>'payee', -(p->>'amount')::bigint
+        from txns t, jsonb_array_elements(t.fields->'payments') p
+        where t.type = 'payment_v2' and t.fields->>'payer' = $3
+        union all
+        select t.block, t.hash, t.type, 'payment', coalesce(p->>'token_type', 'hnt'), t.fields->>'payer', (p->>'amount')::bigint
+        from txns t, jsonb_array_elements(t.fields->'payments') p
+        where t.type = 'payment_v2' and p->>'payee' = $3
+        union all
+        select t.block, t.hash, t.type, 'payment', 'hst', t.fields->>'payee', -(t.fields->>'amount')::bigint
+        from txns t
+        where t.type = 'security_exchange_v1' and t.fields->>'payer' = $3
+        union all
+        select t.block, t.hash, t.type, 'payment', 'hst', t.fields->>'payer', (t.fields->>'amount')::bigint
+        from txns t
+        where t.type = 'security_exchange_v1' and t.fields->>'payee' = $3
+        union all
+        select t.block, t.hash, t.type, 'burn', 'hnt', t.fields->>'payee', -(t.fields->>'amount')::bigint
+        from txns t
+        where t.type = 'token_burn_v1' and t.fields->>'payer' = $3
+        union all
+        select t.block, t.hash, t.type, 'burn', 'dc', t.fields->>'payer',
+            floor((t.fields->>'amount')::numeric * o.price / 100000000000)::bigint
+        from txns t
+        inner join oracle_prices o on o.block = (select max(o2.block) from oracle_prices o2 where o2.block <= t.block)
+        where t.type = 'token_burn_v1' and t.fields->>'payee' = $3
+        union all
+        select t.block, t.hash, t.type, 'stake', s.token, t.fields->>'address', s.sign * (t.fields->>'stake')::bigint
+        from txns t, (values ('hnt', -1), ('staked_hnt', 1)) s(token, sign)
+        where t.type = 'stake_validator_v1' and t.fields->>'owner' = $3
+        union all
+        select t.block, t.hash, t.type, 'unstake', s.token, t.fields->>'address', s.sign * (t.fields->>'stake_amount')::bigint
+        from txns t, (values ('hnt', 1), ('staked_hnt', -1)) s(token, sign)
+        where t.type = 'unstake_validator_v1' and t.fields->>'owner' = $3
+        union all
+        select t.block, t.hash, t.type, 'transfer', 'staked_hnt', t.fields->>'new_owner', -(t.fields->>'stake_amount')::bigint
+        from txns t
+        where t.type = 'transfer_validator_stake_v1' and t.fields->>'old_owner' = $3
+        union all
+        select t.block, t.hash, t.type, 'transfer', 'hnt', t.fields->>'new_owner', (t.fields->>'payment_amount')::bigint
+        from txns t
+        where t.type = 'transfer_validator_stake_v1' and t.fields->>'old_owner' = $3
+            and (t.fields->>'payment_amount')::bigint <> 0
+        union all
+        select t.block, t.hash, t.type, 'transfer', 'staked_hnt', t.fields->>'old_owner', (t.fields->>'stake_amount')::bigint
+        from txns t
+        where t.type = 'transfer_validator_stake_v1' and t.fields->>'new_owner' = $3
+        union all
+        select t.block, t.hash, t.type, 'transfer', 'hnt', t.fields->>'old_owner', -(t.fields->>'payment_amount')::bigint
+        from txns t
+        where t.type = 'transfer_validator_stake_v1' and t.fields->>'new_owner' = $3
+            and (t.fields->>'payment_amount')::bigint <> 0
+        union all
+        select t.block, t.hash, t.type, 'transfer', 'hnt', t.fields->>'seller', -(t.fields->>'amount_to_seller')::bigint
+        from txns t
+        where t.type = 'transfer_hotspot_v1' and t.fields->>'buyer' = $3
+            and (t.fields->>'amount_to_seller')::bigint <> 0
+        union all
+        select t.block, t.hash, t.type, 'transfer', 'hnt', t.fields->>'buyer', (t.fields->>'amount_to_seller')::bigint
+        from txns t
+        where t.type = 'transfer_hotspot_v1' and t.fields->>'seller' = $3
+            and (t.fields->>'amount_to_seller')::bigint <> 0
+        union all
+        select t.block, t.hash, t.type, 'fee', 'dc', null, -(t.fields->>'fee')::bigint
+        from txns t
+        where coalesce(t.fields->>'payer', t.fields->>'owner', t.fields->>'buyer') = $3
+            and (t.fields->>'fee')::bigint > 0
+        union all
+        select r.block, r.transaction_hash, 'rewards', 'reward', 'hnt', nullif(r.gateway, '1Wh4bh'), r.amount
+        from rewards r
+        where r.block between $1 and $2 and r.account = $3
+    ),
+    opening as (
+        select * from accounts
+        where address = $3 and block < $1
+        order by block desc
+        limit 1
+    ),
+    opening_balances as (
+        select b.token, b.balance
+        from opening o, lateral (values
+            ('hnt', o.balance),
+            ('staked_hnt', o.staked_balance),
+            ('hst', o.security_balance),
+            ('mobile', o.mobile_balance),
+            ('iot', o.iot_balance),
+            ('dc', o.dc_balance)
+        ) b(token, balance)
+    ),
+    ledger as (
+        select
+            e.*,
+            coalesce(o.balance, 0) + sum(e.amount) over (
+                partition by e.token
+                order by e.block, e.hash, e.kind, e.counterparty, e.amount
+                rows unbounded preceding
+            ) as balance
+        from entries e
+        left join opening_balances o on o.token = e.token
+    )
+    select
+        l.block,
+        b.timestamp,
+        l.hash as transaction_hash,
+        l.type,
+        l.kind,
+        l.token,
+        l.counterparty,
+        case when l.token = 'dc' then l.amount::float8 else l.amount::float8 / 100000000 end as amount,
+        case when l.token = 'dc' then l.balance::float8 else l.balance::float8 / 100000000 end as balance
+    from ledger l
+    inner join blocks b on b.height = l.block
+    order by l.block, l.hash, l.kind, l.token, l.counterparty, l.amount;
+"#;
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.range.resolve(pool, &opts).await?;
+        let key = format!("accounts-ledger {}", self.account);
+        opts.output_incremental(
+            pool,
+            "accounts-ledger",
+            &key,
+            &format,
+            &span,
+            |_, blockspan| {
+                sqlx::query_as::<_, Entry>(LEDGER_QUERY)
+                    .bind(blockspan.low)
+                    .bind(blockspan.high)
+                    .bind(&self.account)
+                    .fetch(pool)
+            },
+        )
+        .await
+    }
+}
@@ -0,0 +1,18 @@
+use crate::{cmd::Opts, Result};
+use sqlx::PgPool;
+use structopt::StructOpt;
+
+mod ledger;
+
+#[derive(Debug, StructOpt)]
+pub enum Cmd {
+    Ledger(ledger::Cmd),
+}
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        match self {
+            Self::Ledger(cmd) => cmd.run(pool, opts).await,
+        }
+    }
+}
@@ -1,25 +1,43 @@
 use crate::{
-    cmd::{Format, Opts},
-    BlockSpan, Result,
+    cmd::{parquet::ArrowSchema, Format, Opts, SeriesArgs},
+    BlockSpan, Error, Result,
 };
-use chrono::NaiveDate;
+use anyhow::{bail, Context};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::{NaiveDate, Utc};
 use futures::stream::{self, StreamExt, TryStreamExt};
 use sqlx::postgres::PgPool;
-use std::result::Result as StdResult;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Gets the balance of the given account at the given date
+/// Gets the balance of the given account, or of all accounts in a file, at the
+/// given date, or at every date of a series
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 pub struct Cmd {
-    /// The wallet address to look up the balance for
-    account: String,
+    /// The wallet address to look up the balance for. Leave out when using
+    /// --accounts-file.
+    #[structopt(required_unless = "accounts-file")]
+    account: Option<String>,
 
-    /// One or more end dates (exclusive) to run the report over (in UTC). The
-    /// end time is at the beginning midnight of the given date (00:00:00).
+    /// One or more end dates (exclusive) to run the report over (in the --tz
+    /// timezone). The end time is at the beginning midnight of the given date
+    /// (00:00:00).
     end: Vec<NaiveDate>,
 
-    #[structopt(long, default_value)]
-    format: Format,
+    /// A file with the wallet addresses to look up the balances for, either
+    /// one address per line or a CSV file of address and label. Lines starting
+    /// with # are ignored. Replaces the positional account and dates, the
+    /// dates are given by --from and --to.
+    #[structopt(long, conflicts_with = "account", requires = "from")]
+    accounts_file: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    series: SeriesArgs,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
 }
 
 #[derive(Debug, serde::Serialize, sqlx::FromRow)]
@@ -27,7 +45,19 @@ pub struct Balance {
     #[sqlx(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     date: Option<NaiveDate>,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// The block the balance is reported at, the last block before the date
     block: i64,
+    /// The block of the latest change to the account at or before `block`
+    last_block: Option<i64>,
+    /// Whether the balance is carried forward from before the day ending at
+    /// `block` rather than the result of activity on that day
+    carried_forward: bool,
     dc: i64,
     hnt: f64,
     mobile: f64,
@@ -36,47 +66,151 @@ pub struct Balance {
     staked_hnt: f64,
 }
 
+impl ArrowSchema for Balance {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("date", DataType::Date32, true),
+            Field::new("address", DataType::Utf8, true),
+            Field::new("label", DataType::Utf8, true),
+            Field::new("block", DataType::Int64, false),
+            Field::new("last_block", DataType::Int64, true),
+            Field::new("carried_forward", DataType::Boolean, false),
+            Field::new("dc", DataType::Int64, false),
+            Field::new("hnt", DataType::Float64, false),
+            Field::new("mobile", DataType::Float64, false),
+            Field::new("iot", DataType::Float64, false),
+            Field::new("hst", DataType::Float64, false),
+            Field::new("staked_hnt", DataType::Float64, false),
+        ])
+    }
+}
+
+/// An account to look up, as read from the accounts file
+#[derive(Debug, Clone, serde::Deserialize)]
+struct Account {
+    address: String,
+    #[serde(default)]
+    label: Option<String>,
+}
+
+/// Looks up the latest known state of the given accounts as of the high block
+/// of each of the given block spans in one pass. Rows are ordered by span and
+/// then by account.
 const BALANCE_QUERY: &str = r#"
-    select 
-        $2 as block, 
-        greatest(0, max(balance))::float8 / 100000000 as hnt,
-        greatest(0, max(mobile_balance))::float8 / 100000000 as mobile,
-        greatest(0, max(security_balance))::float8 / 100000000 as hst,
-        greatest(0, max(iot_balance))::float8 / 100000000 as iot,
-        greatest(0, max(dc_balance)) as dc,
-        greatest(0, max(staked_balance))::float8 / 100000000 as staked_hnt
-    from accounts
-    where 
-        address = $1 and
-        block > $2 and 
-        block <= $3
+    select
+        h.high as block,
+        a.block as last_block,
+        coalesce(a.block < h.low, true) as carried_forward,
+        greatest(0, coalesce(a.balance, 0))::float8 / 100000000 as hnt,
+        greatest(0, coalesce(a.mobile_balance, 0))::float8 / 100000000 as mobile,
+        greatest(0, coalesce(a.security_balance, 0))::float8 / 100000000 as hst,
+        greatest(0, coalesce(a.iot_balance, 0))::float8 / 100000000 as iot,
+        greatest(0, coalesce(a.dc_balance, 0)) as dc,
+        greatest(0, coalesce(a.staked_balance, 0))::float8 / 100000000 as staked_hnt
+    from unnest($2::bigint[], $3::bigint[]) with ordinality as h(low, high, idx)
+    cross join unnest($1::text[]) with ordinality as w(address, idx)
+    left join lateral (
+        select * from accounts
+        where address = w.address and block <= h.high
+        order by block desc
+        limit 1
+    ) a on true
+    order by h.idx, w.idx
 "#;
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let supplies = stream::iter(self.end.clone())
-            .map(|end| Ok(fetch_balance(pool, &self.account, end)))
-            .try_buffered(10)
-            .boxed();
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let (accounts, dates) = self.accounts_and_dates()?;
+        let dates = self.series.dates(&opts, &dates)?;
+        if let Some(end) = dates.iter().max() {
+            let high = opts.start_of_day(*end).with_timezone(&Utc);
+            opts.ensure_fresh(pool, high).await?;
+        }
+        let (lows, highs): (Vec<i64>, Vec<i64>) = stream::iter(&dates)
+            .map(|end| BlockSpan::from_date(pool, opts.start_of_day(*end), -1))
+            .buffered(opts.concurrency())
+            .map_ok(|blockspan| (blockspan.low, blockspan.high))
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .unzip();
 
-        self.format.output(std::io::stdout(), supplies).await?;
+        let addresses: Vec<&str> = accounts
+            .iter()
+            .map(|account| account.address.as_str())
+            .collect();
+        let balances: Vec<Balance> = sqlx::query_as::<_, Balance>(BALANCE_QUERY)
+            .bind(&addresses)
+            .bind(&lows)
+            .bind(&highs)
+            .fetch_all(pool)
+            .await?;
+        let batch = self.accounts_file.is_some();
+        let keys = dates
+            .into_iter()
+            .flat_map(|date| accounts.iter().map(move |account| (date, account)));
+        let rows = keys
+            .zip(balances)
+            .map(move |((date, account), mut balance)| {
+                balance.date = Some(date);
+                if batch {
+                    balance.address = Some(account.address.clone());
+                    balance.label = account.label.clone();
+                }
+                Ok::<_, Error>(balance)
+            });
+
+        format
+            .output(opts.output("balance", &format)?, stream::iter(rows).boxed())
+            .await?;
         Ok(())
     }
+
+    /// The accounts to look up and the positional dates
+    fn accounts_and_dates(&self) -> Result<(Vec<Account>, Vec<NaiveDate>)> {
+        match &self.accounts_file {
+            Some(path) => Ok((read_accounts(path)?, vec![])),
+            None => {
+                let account = Account {
+                    address: self.account.clone().unwrap_or_default(),
+                    label: None,
+                };
+                Ok((vec![account], self.end.clone()))
+            }
+        }
+    }
 }
 
-async fn fetch_balance(
-    pool: &PgPool,
-    account: &str,
-    end: NaiveDate,
-) -> StdResult<Balance, sqlx::Error> {
-    let start = end - chrono::Duration::days(1);
-    let blockspan = BlockSpan::for_date_range(pool, start, end).await?;
-    let mut balance: Balance = sqlx::query_as::<_, Balance>(BALANCE_QUERY)
-        .bind(account)
-        .bind(blockspan.low)
-        .bind(blockspan.high)
-        .fetch_one(pool)
-        .await?;
-    balance.date = Some(end);
-    Ok(balance)
+/// Reads the accounts in the given file. A label column is only reported when
+/// at least one line of the file has a label, in which case accounts without
+/// one get an empty label.
+fn read_accounts(path: &Path) -> Result<Vec<Account>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .with_context(|| format!("reading accounts file {}", path.display()))?;
+    let mut accounts = Vec::new();
+    let mut labeled = false;
+    for record in reader.deserialize() {
+        let account: Account =
+            record.with_context(|| format!("reading accounts file {}", path.display()))?;
+        if account.address.is_empty() || account.address.eq_ignore_ascii_case("address") {
+            continue;
+        }
+        labeled |= account.label.is_some();
+        accounts.push(account);
+    }
+    if accounts.is_empty() {
+        bail!("no accounts in {}", path.display());
+    }
+    if labeled {
+        for account in &mut accounts {
+            account.label.get_or_insert_with(String::new);
+        }
+    }
+    Ok(accounts)
 }
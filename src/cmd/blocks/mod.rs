@@ -1,18 +1,39 @@
-use crate::{cmd::Opts, Result};
+use crate::{
+    cmd::{print_json, Format, Opts},
+    BlockSpan, Result, TimeSpan,
+};
+use serde_json::json;
 use sqlx::PgPool;
 use structopt::StructOpt;
 
 mod span;
+mod time;
 
 #[derive(Debug, StructOpt)]
 pub enum Cmd {
     Span(span::Cmd),
+    Time(time::Cmd),
 }
 
 impl Cmd {
     pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
         match self {
             Self::Span(cmd) => cmd.run(pool, opts).await,
+            Self::Time(cmd) => cmd.run(pool, opts).await,
         }
     }
 }
+
+fn print_blockspan(opts: &Opts, name: &str, timespan: &TimeSpan, blockspan: &BlockSpan) -> Result {
+    let tz = opts.tz();
+    let json = json!({
+        "blockspan": blockspan,
+        "timespan": timespan,
+        "local_timespan": {
+            "low": timespan.low.with_timezone(&tz),
+            "high": timespan.high.with_timezone(&tz),
+            "timezone": tz.name(),
+        },
+    });
+    print_json(opts.output(name, &Format::Json)?, &json)
+}
@@ -1,38 +1,23 @@
+use super::print_blockspan;
 use crate::{
-    cmd::{print_json, Opts},
-    BlockSpan, Result, TimeSpan,
+    cmd::{DateArgs, Opts},
+    Result,
 };
-use chrono::NaiveDate;
-use serde_json::json;
 use sqlx::postgres::PgPool;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Look up the block range for a given date in UTC. If no date is specified the
-/// current UTC date is used.
+/// Look up the block range for a given date in the --tz timezone.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 pub struct Cmd {
-    /// The day to start the timespan at. The start time of the date is at
-    /// midnight UTC.
-    date: NaiveDate,
-
-    /// The number of days to include in the timespan. Days can be positive or
-    /// negative.
-    #[structopt(default_value = "1")]
-    days: i64,
+    #[structopt(flatten)]
+    date: DateArgs,
 }
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let timespan = TimeSpan::new(self.date, self.days);
-        let blockspan = BlockSpan::for_timespan(pool, &timespan).await?;
-        print_blockspan(&timespan, &blockspan)
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let span = self.date.resolve(pool, &opts, 1).await?;
+        let blockspan = span.blockspan(pool, &span.timespan).await?;
+        print_blockspan(&opts, "blocks-span", &span.timespan, &blockspan)
     }
 }
-
-fn print_blockspan(timespan: &TimeSpan, blockspan: &BlockSpan) -> Result {
-    let json = json!({
-        "blockspan": blockspan,
-        "timespan": timespan,
-    });
-    print_json(&json)
-}
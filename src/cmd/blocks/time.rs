@@ -0,0 +1,30 @@
+use super::print_blockspan;
+use crate::{cmd::Opts, BlockSpan, Result};
+use anyhow::bail;
+use sqlx::postgres::PgPool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Look up the timestamp of a block, or the timespan from the first to the last
+/// block of a range of blocks.
+pub struct Cmd {
+    /// The block height to look up, or the first block (inclusive) of a range
+    low: i64,
+
+    /// The last block (inclusive) of the range
+    high: Option<i64>,
+}
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let blockspan = BlockSpan {
+            low: self.low,
+            high: self.high.unwrap_or(self.low),
+        };
+        if blockspan.low > blockspan.high {
+            bail!("block {} is above block {}", blockspan.low, blockspan.high);
+        }
+        let timespan = blockspan.timespan(pool).await?;
+        print_blockspan(&opts, "blocks-time", &timespan, &blockspan)
+    }
+}
@@ -0,0 +1,17 @@
+use crate::{cache::Cache, cmd::Opts, Result};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Manage the local cache of block heights that timestamps resolve to.
+pub enum Cmd {
+    /// Removes all cached block heights
+    Clear,
+}
+
+impl Cmd {
+    pub fn run(&self, opts: Opts) -> Result {
+        match self {
+            Self::Clear => Cache::clear(opts.cache_file()),
+        }
+    }
+}
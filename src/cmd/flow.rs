@@ -0,0 +1,124 @@
+use crate::{
+    cmd::{parquet::ArrowSchema, Format, Opts, RangeArgs},
+    Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use sqlx::postgres::PgPool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Generates output in any --format with the inbound and outbound token flows
+/// of a given wallet, grouped by counterparty. Payments, token burns and
+/// validator stakes are included. A total row (without a counterparty) is
+/// emitted for every direction and token.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+pub struct Cmd {
+    /// The wallet address to report token flows for
+    account: String,
+
+    #[structopt(flatten)]
+    range: RangeArgs,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct Flow {
+    direction: String,
+    token: String,
+    kind: Option<String>,
+    counterparty: Option<String>,
+    count: i64,
+    amount: f64,
+}
+
+impl ArrowSchema for Flow {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("direction", DataType::Utf8, false),
+            Field::new("token", DataType::Utf8, false),
+            Field::new("kind", DataType::Utf8, true),
+            Field::new("counterparty", DataType::Utf8, true),
+            Field::new("count", DataType::Int64, false),
+            Field::new("amount", DataType::Float64, false),
+        ])
+    }
+}
+
+const FLOW_QUERY: &str = r#"
+    with txns as (
+        select distinct t.hash, t.type, t.fields
+        from transaction_actors a
+        inner join transactions t on t.hash = a.transaction_hash
+        where a.block between $1 and $2
+            and a.actor = $3
+            and t.type in (
+                'payment_v1',
+                'payment_v2',
+                'token_burn_v1',
+                'stake_validator_v1',
+                'unstake_validator_v1'
+            )
+    ),
+    transfers as (
+        select
+            'out' as direction,
+            'payment' as kind,
+            t.fields->>'payee' as counterparty,
+            'hnt' as token,
+            (t.fields->>'amount')::bigint as amount
+        from txns t
+        where t.type = 'payment_v1' and t.fields->>'payer' = $3
+        union all
+        select 'in', 'payment', t.fields->>'payer', 'hnt', (t.fields->>'amount')::bigint
+        from txns t
+        where t.type = 'payment_v1' and t.fields->>'payee' = $3
+        union all
+        select 'out', 'payment', p->>'payee', coalesce(p->>'token_type', 'hnt'), (p->>'amount')::bigint
+        from txns t, jsonb_array_elements(t.fields->'payments') p
+        where t.type = 'payment_v2' and t.fields->>'payer' = $3
+        union all
+        select 'in', 'payment', t.fields->>'payer', coalesce(p->>'token_type', 'hnt'), (p->>'amount')::bigint
+        from txns t, jsonb_array_elements(t.fields->'payments') p
+        where t.type = 'payment_v2' and p->>'payee' = $3
+        union all
+        select 'out', 'burn', t.fields->>'payee', 'hnt', (t.fields->>'amount')::bigint
+        from txns t
+        where t.type = 'token_burn_v1' and t.fields->>'payer' = $3
+        union all
+        select 'out', 'stake', t.fields->>'address', 'hnt', (t.fields->>'stake')::bigint
+        from txns t
+        where t.type = 'stake_validator_v1' and t.fields->>'owner' = $3
+        union all
+        select 'in', 'unstake', t.fields->>'address', 'hnt', (t.fields->>'stake_amount')::bigint
+        from txns t
+        where t.type = 'unstake_validator_v1' and t.fields->>'owner' = $3
+    )
+    select
+        direction,
+        token,
+        kind,
+        counterparty,
+        count(*) as count,
+        sum(amount)::float8 / 100000000 as amount
+    from transfers
+    group by grouping sets ((direction, token, kind, counterparty), (direction, token))
+    order by direction, token, grouping(counterparty), amount desc;
+"#;
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.range.resolve(pool, &opts).await?;
+        opts.output_timespan(pool, "flow", &format, &span, |_, blockspan| {
+            sqlx::query_as::<_, Flow>(FLOW_QUERY)
+                .bind(blockspan.low)
+                .bind(blockspan.high)
+                .bind(&self.account)
+                .fetch(pool)
+        })
+        .await
+    }
+}
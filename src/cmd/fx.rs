@@ -0,0 +1,156 @@
+use crate::Result;
+use anyhow::{anyhow, bail, Context};
+use chrono::NaiveDate;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+// Options to convert USD amounts to another currency using a local file of
+// daily exchange rates. Not a doc comment, see SpanArgs.
+#[derive(Debug, StructOpt)]
+pub struct FxArgs {
+    /// The currency to convert USD amounts to, e.g. EUR or CHF. Requires
+    /// --fx-rates.
+    #[structopt(long, requires = "fx-rates")]
+    currency: Option<String>,
+
+    /// A CSV file with date, currency and rate columns and a header row, where
+    /// the rate is the amount of the currency that one USD buys on that date
+    /// (in the --tz timezone)
+    #[structopt(long, requires = "currency")]
+    fx_rates: Option<PathBuf>,
+}
+
+/// The daily exchange rates from USD to a single currency
+pub struct FxRates {
+    currency: String,
+    path: PathBuf,
+    rates: HashMap<NaiveDate, f64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Rate {
+    date: NaiveDate,
+    currency: String,
+    rate: f64,
+}
+
+impl FxArgs {
+    /// Loads the rates for the requested currency, if any
+    pub fn load(&self) -> Result<Option<FxRates>> {
+        match (&self.currency, &self.fx_rates) {
+            (Some(currency), Some(path)) => FxRates::load(path, currency).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl FxRates {
+    pub fn load(path: &Path, currency: &str) -> Result<Self> {
+        let currency = currency.to_ascii_uppercase();
+        let context = || format!("reading fx rates {}", path.display());
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .with_context(context)?;
+        let mut rates = HashMap::new();
+        for record in reader.deserialize() {
+            let rate: Rate = record.with_context(context)?;
+            if !rate.currency.eq_ignore_ascii_case(&currency) {
+                continue;
+            }
+            if rates.insert(rate.date, rate.rate).is_some() {
+                bail!(
+                    "duplicate {currency} rate for {} in {}",
+                    rate.date,
+                    path.display()
+                );
+            }
+        }
+        if rates.is_empty() {
+            bail!("no {currency} rates in {}", path.display());
+        }
+        Ok(Self {
+            currency,
+            path: path.to_path_buf(),
+            rates,
+        })
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// The rate on the given date
+    pub fn rate(&self, date: NaiveDate) -> Result<f64> {
+        self.rates.get(&date).copied().ok_or_else(|| {
+            anyhow!(
+                "no {} rate for {date} in {}",
+                self.currency,
+                self.path.display()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn rates_file(csv: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(csv.as_bytes()).unwrap();
+        file
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn loads_rates_of_the_currency() {
+        let file = rates_file(
+            "date,currency,rate\n\
+             2023-01-01, eur, 0.9\n\
+             2023-01-01,CHF,0.95\n\
+             2023-01-02,EUR,0.8\n",
+        );
+        let fx = FxRates::load(file.path(), "Eur").unwrap();
+        assert_eq!(fx.currency(), "EUR");
+        assert_eq!(fx.rate(date("2023-01-01")).unwrap(), 0.9);
+        assert_eq!(fx.rate(date("2023-01-02")).unwrap(), 0.8);
+    }
+
+    #[test]
+    fn fails_on_a_missing_day() {
+        let file = rates_file("date,currency,rate\n2023-01-01,EUR,0.9\n");
+        let fx = FxRates::load(file.path(), "EUR").unwrap();
+        let err = fx.rate(date("2023-01-02")).unwrap_err();
+        assert!(err.to_string().starts_with("no EUR rate for 2023-01-02"));
+    }
+
+    #[test]
+    fn rejects_duplicate_dates() {
+        let file = rates_file(
+            "date,currency,rate\n\
+             2023-01-01,EUR,0.9\n\
+             2023-01-01,CHF,0.95\n\
+             2023-01-01,eur,0.91\n",
+        );
+        let err = FxRates::load(file.path(), "EUR").err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("duplicate EUR rate for 2023-01-01"));
+        assert!(FxRates::load(file.path(), "CHF").is_ok());
+    }
+
+    #[test]
+    fn rejects_a_currency_without_rates() {
+        let file = rates_file("date,currency,rate\n2023-01-01,EUR,0.9\n");
+        let err = FxRates::load(file.path(), "GBP").err().unwrap();
+        assert!(err.to_string().starts_with("no GBP rates in"));
+    }
+}
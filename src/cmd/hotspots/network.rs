@@ -1,14 +1,21 @@
-use crate::{cmd::Opts, Result};
-use futures::TryStreamExt;
+use crate::{
+    cmd::{parquet::ArrowSchema, Format, Opts},
+    Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::stream::StreamExt;
 use h3ron::{H3Cell, ToCoordinate};
-use serde::{ser::SerializeSeq, Serializer};
 use sqlx::postgres::PgPool;
 use std::str::FromStr;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Generates JSON output with all hotspots
-pub struct Cmd {}
+/// Generates output in any --format with all hotspots
+pub struct Cmd {
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
 
 #[derive(sqlx::Type, Debug, serde::Serialize, serde::Deserialize)]
 #[sqlx(type_name = "gateway_status_online", rename_all = "lowercase")]
@@ -39,16 +46,31 @@ pub struct Hotspot {
     lat: Option<f64>,
     #[sqlx(default)]
     lng: Option<f64>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     short_street: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     short_city: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     short_state: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     short_country: Option<String>,
 }
 
+impl ArrowSchema for Hotspot {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("address", DataType::Utf8, false),
+            Field::new("mode", DataType::Utf8, false),
+            Field::new("owner", DataType::Utf8, false),
+            Field::new("location", DataType::Utf8, true),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("online", DataType::Utf8, false),
+            Field::new("lat", DataType::Float64, true),
+            Field::new("lng", DataType::Float64, true),
+            Field::new("short_street", DataType::Utf8, true),
+            Field::new("short_city", DataType::Utf8, true),
+            Field::new("short_state", DataType::Utf8, true),
+            Field::new("short_country", DataType::Utf8, true),
+        ])
+    }
+}
+
 const HOTSPOTS_QUERY: &str = r#"
     select
         g.address,
@@ -68,19 +90,23 @@ const HOTSPOTS_QUERY: &str = r#"
 "#;
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let mut rows = sqlx::query_as::<_, Hotspot>(HOTSPOTS_QUERY).fetch(pool);
-        let mut serializer = serde_json::Serializer::pretty(std::io::stdout());
-        let mut entries = serializer.serialize_seq(None)?;
-        while let Some(mut hotspot) = rows.try_next().await? {
-            if let Some(location) = &hotspot.location {
-                let (lng, lat) = H3Cell::from_str(location)?.to_coordinate()?.x_y();
-                hotspot.lng = Some(lng);
-                hotspot.lat = Some(lat);
-            }
-            entries.serialize_element(&hotspot)?;
-        }
-        entries.end()?;
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let rows = sqlx::query_as::<_, Hotspot>(HOTSPOTS_QUERY)
+            .fetch(pool)
+            .map(|row| -> Result<Hotspot> {
+                let mut hotspot = row?;
+                if let Some(location) = &hotspot.location {
+                    let (lng, lat) = H3Cell::from_str(location)?.to_coordinate()?.x_y();
+                    hotspot.lng = Some(lng);
+                    hotspot.lat = Some(lat);
+                }
+                Ok(hotspot)
+            })
+            .boxed();
+        format
+            .output(opts.output("hotspots-network", &format)?, rows)
+            .await?;
         Ok(())
     }
 }
@@ -1,63 +1,466 @@
-use crate::Result;
+use crate::{
+    cache::Cache, config::Config, timespan, BlockSpan, BlockSpanError, ChainBounds, Partition,
+    Result, TimeSpan,
+};
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
 use futures::stream::{BoxStream, TryStreamExt};
-use std::path::PathBuf;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 use structopt::StructOpt;
 
-pub mod blocks;
-// pub mod flow;
+pub mod accounts;
 pub mod balance;
+pub mod blocks;
+pub mod cache;
+pub mod flow;
+pub mod fx;
 pub mod hotspots;
+pub mod oracle;
+pub mod output;
+pub mod parquet;
 pub mod rewards;
+pub mod span;
+pub mod state;
+pub mod status;
 pub mod supply;
+pub mod tax;
+pub mod trace;
+
+pub use fx::{FxArgs, FxRates};
+pub use output::Output;
+pub use span::{DateArgs, RangeArgs, SeriesArgs, Span, SpanArgs};
+use state::State;
 
 /// Common options for most commands
 #[derive(Debug, StructOpt)]
 pub struct Opts {
     #[structopt(short = "e")]
     pub env: Option<PathBuf>,
+
+    /// The config file to read profiles from. Defaults to etl-extract.toml in
+    /// the current directory if it exists.
+    #[structopt(long)]
+    pub config: Option<PathBuf>,
+
+    /// The config profile to use. Defaults to the "default" profile if it
+    /// exists.
+    #[structopt(long)]
+    pub profile: Option<String>,
+
+    /// The database to connect to. Overrides the DATABASE_URL environment
+    /// variable.
+    #[structopt(long)]
+    pub database_url: Option<String>,
+
+    /// A read-only replica to run all queries against instead of the
+    /// database url. Overrides the READ_DATABASE_URL environment variable.
+    /// A read url from the environment or the profile is ignored when the
+    /// database url is given at a higher precedence level.
+    #[structopt(long)]
+    pub read_database_url: Option<String>,
+
+    /// The maximum number of database connections to open
+    #[structopt(long)]
+    pub max_connections: Option<u32>,
+
+    /// How long to wait for a database connection, e.g. "30s"
+    #[structopt(long)]
+    pub acquire_timeout: Option<humantime::Duration>,
+
+    /// Cancel any query that runs for longer than the given duration, e.g.
+    /// "10m"
+    #[structopt(long)]
+    pub statement_timeout: Option<humantime::Duration>,
+
+    /// The application name reported to the database [default: etl-extract]
+    #[structopt(long)]
+    pub application_name: Option<String>,
+
+    /// The number of queries to run concurrently in commands that issue more
+    /// than one query [default: 10]
+    #[structopt(long)]
+    pub concurrency: Option<usize>,
+
+    /// Refuse to run when the latest ingested block is older than the given
+    /// duration, e.g. "15m", or when it is before the end of the requested
+    /// timespan so the timespan has not been fully ingested
+    #[structopt(long)]
+    pub require_fresh: Option<humantime::Duration>,
+
+    /// The IANA timezone dates are interpreted in, e.g. "America/New_York"
+    /// [default: UTC]
+    #[structopt(long)]
+    pub tz: Option<Tz>,
+
+    /// The default output format, set from the config profile
+    #[structopt(skip)]
+    pub format: Option<Format>,
+
+    /// File or directory to write output to, or "-" for stdout. Files ending
+    /// in .gz or .zst are compressed. When a directory is given the file is
+    /// named after the command and output format.
+    #[structopt(short = "o", long)]
+    pub output: Option<PathBuf>,
+
+    /// Split the output of commands that run over a timespan into one file per
+    /// day, week or month. Files are written to the --output directory in
+    /// date=YYYY-MM-DD sub-directories.
+    #[structopt(long)]
+    pub partition_by: Option<Partition>,
+
+    /// Only export blocks above the checkpoint recorded by a previous run and
+    /// append them to the existing --output file. Requires csv or ndjson
+    /// output.
+    #[structopt(long)]
+    pub incremental: bool,
+
+    /// The file checkpoints of incremental runs are kept in
+    #[structopt(long, default_value = ".etl-extract-state.json")]
+    pub state_file: PathBuf,
+
+    /// The file block heights that timestamps resolve to are cached in
+    /// [default: .etl-extract-cache.db]
+    #[structopt(long)]
+    pub cache_file: Option<PathBuf>,
+
+    /// Always look up block heights in the database
+    #[structopt(long)]
+    pub no_cache: bool,
+}
+
+impl Opts {
+    /// Fills in settings that were not given on the command line from the
+    /// environment and the selected config profile.
+    pub fn load_config(&mut self) -> Result {
+        let profile = Config::load_profile(self.config.as_deref(), self.profile.as_deref())?;
+        // The read url is only taken from the level the database url comes
+        // from or a higher one, so that an explicit database url is not
+        // bypassed by an inherited read url
+        if self.database_url.is_none() {
+            let read_database_url = self
+                .read_database_url
+                .take()
+                .or_else(|| dotenv::var("READ_DATABASE_URL").ok());
+            match dotenv::var("DATABASE_URL") {
+                Ok(url) => {
+                    self.database_url = Some(url);
+                    self.read_database_url = read_database_url;
+                }
+                Err(_) => {
+                    self.database_url = profile.database_url;
+                    self.read_database_url = read_database_url.or(profile.read_database_url);
+                }
+            }
+        }
+        self.max_connections = self.max_connections.or(profile.max_connections);
+        if self.acquire_timeout.is_none() {
+            self.acquire_timeout = profile.acquire_timeout.map(|s| s.parse()).transpose()?;
+        }
+        if self.statement_timeout.is_none() {
+            self.statement_timeout = profile.statement_timeout.map(|s| s.parse()).transpose()?;
+        }
+        self.application_name = self.application_name.take().or(profile.application_name);
+        if self.require_fresh.is_none() {
+            self.require_fresh = profile.require_fresh.map(|s| s.parse()).transpose()?;
+        }
+        if self.tz.is_none() {
+            self.tz = profile.timezone.map(|s| s.parse()).transpose()?;
+        }
+        if let Some(format) = profile.format {
+            self.format = Some(format.parse()?);
+        }
+        self.output = self.output.take().or(profile.output);
+        self.concurrency = self.concurrency.or(profile.concurrency);
+        self.cache_file = self.cache_file.take().or(profile.cache_file);
+        Ok(())
+    }
+
+    pub fn database_url(&self) -> Result<&str> {
+        self.read_database_url
+            .as_deref()
+            .or(self.database_url.as_deref())
+            .ok_or_else(|| anyhow!("no database url, set DATABASE_URL or --database-url"))
+    }
+
+    /// Opens a connection pool to the configured database
+    pub async fn connect(&self) -> Result<PgPool> {
+        let mut options = PgConnectOptions::from_str(self.database_url()?)?
+            .application_name(self.application_name.as_deref().unwrap_or("etl-extract"));
+        if let Some(timeout) = self.statement_timeout {
+            let timeout = format!("{}ms", timeout.as_millis());
+            options = options.options([("statement_timeout", timeout)]);
+        }
+        let mut pool = PgPoolOptions::new();
+        if let Some(max_connections) = self.max_connections {
+            pool = pool.max_connections(max_connections);
+        }
+        if let Some(timeout) = self.acquire_timeout {
+            pool = pool.acquire_timeout(timeout.into());
+        }
+        Ok(pool.connect_with(options).await?)
+    }
+
+    pub fn cache_file(&self) -> &Path {
+        self.cache_file
+            .as_deref()
+            .unwrap_or(Path::new(".etl-extract-cache.db"))
+    }
+
+    /// Opens the block height cache for the configured database, unless
+    /// --no-cache is given
+    pub async fn open_cache(&self) -> Result {
+        if self.no_cache {
+            return Ok(());
+        }
+        let options = PgConnectOptions::from_str(self.database_url()?)?;
+        let database = format!(
+            "{}:{}/{}",
+            options.get_host(),
+            options.get_port(),
+            options.get_database().unwrap_or_default()
+        );
+        Cache::open(self.cache_file(), &database).await?.install();
+        Ok(())
+    }
+
+    /// The given command format, or the configured default format
+    pub fn format(&self, format: Option<Format>) -> Format {
+        format.or(self.format).unwrap_or_default()
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.tz.unwrap_or(Tz::UTC)
+    }
+
+    /// The start of the given date in the configured timezone
+    pub fn start_of_day(&self, date: NaiveDate) -> DateTime<Tz> {
+        timespan::start_of_day(&self.tz(), date)
+    }
+
+    /// Checks that the etl has ingested blocks up to the given time when
+    /// --require-fresh is set. See [`Opts::require_fresh`].
+    pub async fn ensure_fresh(&self, pool: &PgPool, high: DateTime<Utc>) -> Result {
+        let Some(limit) = self.require_fresh else {
+            return Ok(());
+        };
+        let bounds = ChainBounds::fetch(pool).await?;
+        let limit = Duration::from_std(limit.into())?;
+        let lag = Utc::now() - bounds.tip_timestamp;
+        if lag > limit {
+            bail!(
+                "the latest block {} is from {}, {} ago, which is more than --require-fresh {}",
+                bounds.tip_height,
+                bounds.tip_timestamp,
+                format_duration(lag),
+                format_duration(limit),
+            );
+        }
+        if bounds.tip_timestamp < high {
+            bail!(
+                "the timespan ends at {high} but the latest block {} is from {}, \
+                 the timespan has not been fully ingested yet",
+                bounds.tip_height,
+                bounds.tip_timestamp,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or(10)
+    }
+
+    /// Opens the output for the command with the given name
+    pub fn output(&self, name: &str, format: &Format) -> Result<Output> {
+        if self.partition_by.is_some() {
+            bail!("--partition-by is not supported by {name}");
+        }
+        if self.incremental {
+            bail!("--incremental is not supported by {name}");
+        }
+        match self.output_path(name, format) {
+            None => Ok(Output::stdout()),
+            Some(path) => Output::create(&path),
+        }
+    }
+
+    /// The file to write output to, or None for stdout
+    fn output_path(&self, name: &str, format: &Format) -> Option<PathBuf> {
+        match self.output.as_deref() {
+            None => None,
+            Some(path) if path == Path::new("-") => None,
+            Some(path) if path.is_dir() => Some(path.join(format!("{name}.{format}"))),
+            Some(path) => Some(path.to_path_buf()),
+        }
+    }
+
+    /// Resolves the block span for the given span and writes the rows
+    /// produced for it to the command output. When partitioning is enabled
+    /// the timespan is split up and each partition is written to its own file.
+    /// Partitions without blocks, like the days after the latest block, are
+    /// skipped as long as the timespan as a whole has blocks.
+    pub async fn output_timespan<'a, E, Er, F>(
+        &self,
+        pool: &PgPool,
+        name: &str,
+        format: &Format,
+        span: &Span,
+        mut rows: F,
+    ) -> Result
+    where
+        E: serde::Serialize + parquet::ArrowSchema,
+        crate::Error: From<Er>,
+        F: FnMut(&TimeSpan, BlockSpan) -> BoxStream<'a, std::result::Result<E, Er>>,
+    {
+        let timespan = &span.timespan;
+        let Some(partition) = self.partition_by else {
+            let blockspan = span.blockspan(pool, timespan).await?;
+            let output = self.output(name, format)?;
+            return format.output(output, rows(timespan, blockspan)).await;
+        };
+        let dir = match self.output.as_deref() {
+            Some(dir) if dir.is_dir() => dir,
+            _ => bail!("--partition-by requires --output to be an existing directory"),
+        };
+        span.blockspan(pool, timespan).await?;
+        let tz = self.tz();
+        for part in timespan.partition(partition, &tz) {
+            let blockspan = match span.blockspan(pool, &part).await {
+                Ok(blockspan) if blockspan.low <= blockspan.high => blockspan,
+                Ok(_) => continue,
+                Err(err)
+                    if err
+                        .downcast_ref::<BlockSpanError>()
+                        .is_some_and(BlockSpanError::is_no_blocks) =>
+                {
+                    continue
+                }
+                Err(err) => return Err(err),
+            };
+            let date = part.low.with_timezone(&tz).date_naive();
+            let partition_dir = dir.join(format!("date={date}"));
+            std::fs::create_dir_all(&partition_dir)?;
+            let output = Output::create(&partition_dir.join(format!("{name}.{format}")))?;
+            format.output(output, rows(&part, blockspan)).await?;
+        }
+        Ok(())
+    }
+
+    /// Like [`Opts::output_timespan`], but when running incrementally only the
+    /// blocks above the checkpoint recorded for the given key are exported and
+    /// appended to the existing output. The key should identify the command
+    /// and its arguments other than the timespan.
+    pub async fn output_incremental<'a, E, Er, F>(
+        &self,
+        pool: &PgPool,
+        name: &str,
+        key: &str,
+        format: &Format,
+        span: &Span,
+        mut rows: F,
+    ) -> Result
+    where
+        E: serde::Serialize + parquet::ArrowSchema,
+        crate::Error: From<Er>,
+        F: FnMut(&TimeSpan, BlockSpan) -> BoxStream<'a, std::result::Result<E, Er>>,
+    {
+        if !self.incremental {
+            return self.output_timespan(pool, name, format, span, rows).await;
+        }
+        if self.partition_by.is_some() {
+            bail!("--incremental can not be combined with --partition-by");
+        }
+        if !matches!(format, Format::Csv | Format::Ndjson) {
+            bail!("--incremental requires csv or ndjson output");
+        }
+        let Some(path) = self.output_path(name, format) else {
+            bail!("--incremental requires an --output file");
+        };
+        let key = format!("{key} > {}", path.display());
+        let mut state = State::load(&self.state_file)?;
+        let timespan = &span.timespan;
+        let mut blockspan = span.blockspan(pool, timespan).await?;
+        if let Some(checkpoint) = state.checkpoint(&key).filter(|_| path.exists()) {
+            blockspan.low = blockspan.low.max(checkpoint.block + 1);
+        }
+        if blockspan.low > blockspan.high {
+            return Ok(());
+        }
+        let high = blockspan.high;
+        format
+            .output(Output::append(&path)?, rows(timespan, blockspan))
+            .await?;
+        state.set_checkpoint(&key, high);
+        state.save(&self.state_file)
+    }
 }
 
-pub fn print_json<T: ?Sized + serde::Serialize>(value: &T) -> Result {
-    println!("{}", serde_json::to_string_pretty(value)?);
-    Ok(())
+/// Formats a duration in whole seconds, e.g. "1h 2m 3s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_seconds().max(0) as u64;
+    humantime::format_duration(std::time::Duration::from_secs(seconds)).to_string()
 }
 
-#[derive(Debug, Default)]
+pub fn print_json<T: ?Sized + serde::Serialize>(mut output: Output, value: &T) -> Result {
+    serde_json::to_writer_pretty(&mut output, value)?;
+    writeln!(output)?;
+    output.finish()
+}
+
+#[derive(Debug, Default, Clone, Copy)]
 pub enum Format {
     #[default]
     Json,
+    Ndjson,
     Csv,
+    Parquet,
 }
 
 impl Format {
-    pub async fn output<'a, W, E>(
+    pub async fn output<'a, E, Er>(
         &self,
-        output: W,
-        mut rows: BoxStream<'a, std::result::Result<E, sqlx::Error>>,
+        mut output: Output,
+        mut rows: BoxStream<'a, std::result::Result<E, Er>>,
     ) -> Result
     where
-        W: std::io::Write,
-        E: serde::Serialize,
+        E: serde::Serialize + parquet::ArrowSchema,
+        crate::Error: From<Er>,
     {
         match self {
             Self::Json => {
                 use serde::{ser::SerializeSeq, Serializer};
-                let mut serializer = serde_json::Serializer::pretty(output);
+                let mut serializer = serde_json::Serializer::pretty(&mut output);
                 let mut entries = serializer.serialize_seq(None)?;
                 while let Some(row) = rows.try_next().await? {
                     entries.serialize_element(&row)?;
                 }
                 entries.end()?;
             }
+            Self::Ndjson => {
+                while let Some(row) = rows.try_next().await? {
+                    serde_json::to_writer(&mut output, &row)?;
+                    output.write_all(b"\n")?;
+                }
+                output.flush()?;
+            }
             Self::Csv => {
-                let mut serializer = csv::Writer::from_writer(output);
+                let mut serializer = csv::WriterBuilder::new()
+                    .has_headers(!output.is_append())
+                    .from_writer(&mut output);
                 while let Some(row) = rows.try_next().await? {
                     serializer.serialize(&row)?;
                 }
                 serializer.flush()?;
             }
+            Self::Parquet => parquet::output(&mut output, rows).await?,
         }
-        Ok(())
+        output.finish()
     }
 }
 
@@ -68,16 +471,20 @@ impl std::str::FromStr for Format {
         match s.to_ascii_lowercase().as_str() {
             "csv" => Ok(Self::Csv),
             "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "parquet" => Ok(Self::Parquet),
             _ => Err(anyhow!("invalid format {s}")),
         }
     }
 }
 
-impl ToString for Format {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Self::Csv => "csv".to_string(),
-            Self::Json => "json".to_string(),
+            Self::Csv => f.write_str("csv"),
+            Self::Json => f.write_str("json"),
+            Self::Ndjson => f.write_str("ndjson"),
+            Self::Parquet => f.write_str("parquet"),
         }
     }
 }
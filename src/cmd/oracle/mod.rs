@@ -0,0 +1,18 @@
+use crate::{cmd::Opts, Result};
+use sqlx::PgPool;
+use structopt::StructOpt;
+
+mod prices;
+
+#[derive(Debug, StructOpt)]
+pub enum Cmd {
+    Prices(prices::Cmd),
+}
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        match self {
+            Self::Prices(cmd) => cmd.run(pool, opts).await,
+        }
+    }
+}
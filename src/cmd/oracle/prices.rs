@@ -0,0 +1,289 @@
+use crate::{
+    cmd::{
+        parquet::{timestamp_field, ArrowSchema},
+        Format, Opts, RangeArgs,
+    },
+    ChainBounds, Error, Partition, Result, TimeSpan,
+};
+use arrow_schema::{DataType, Field, Schema};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures::stream::{self, StreamExt};
+use sqlx::postgres::PgPool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Generates output in any --format with the oracle price series over a
+/// timespan, or with its daily open, high, low, close and time-weighted average
+/// price.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+pub struct Cmd {
+    #[structopt(flatten)]
+    range: RangeArgs,
+
+    /// Report the open, high, low, close and time-weighted average price of
+    /// every day (in the --tz timezone) instead of every price. A price is in
+    /// effect from its block until the next price, starting with the price in
+    /// effect at the start of the timespan.
+    #[structopt(long)]
+    daily: bool,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct Price {
+    block: i64,
+    timestamp: DateTime<Utc>,
+    price: f64,
+}
+
+impl ArrowSchema for Price {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("block", DataType::Int64, false),
+            timestamp_field("timestamp", false),
+            Field::new("price", DataType::Float64, false),
+        ])
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DailyPrice {
+    date: NaiveDate,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    twap: f64,
+    /// The number of prices reported on the day
+    count: i64,
+}
+
+impl ArrowSchema for DailyPrice {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("date", DataType::Date32, false),
+            Field::new("open", DataType::Float64, false),
+            Field::new("high", DataType::Float64, false),
+            Field::new("low", DataType::Float64, false),
+            Field::new("close", DataType::Float64, false),
+            Field::new("twap", DataType::Float64, false),
+            Field::new("count", DataType::Int64, false),
+        ])
+    }
+}
+
+const PRICES_QUERY: &str = r#"
+    select o.block, b.timestamp, o.price::float8 / 100000000 as price
+    from oracle_prices o
+    inner join blocks b on b.height = o.block
+    where o.block between $1 and $2
+    order by o.block;
+"#;
+
+const PREVIOUS_PRICE_QUERY: &str = r#"
+    select o.block, b.timestamp, o.price::float8 / 100000000 as price
+    from oracle_prices o
+    inner join blocks b on b.height = o.block
+    where o.block < $1
+    order by o.block desc
+    limit 1;
+"#;
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.range.resolve(pool, &opts).await?;
+        if !self.daily {
+            return opts
+                .output_incremental(
+                    pool,
+                    "oracle-prices",
+                    "oracle-prices",
+                    &format,
+                    &span,
+                    |_, blockspan| {
+                        sqlx::query_as::<_, Price>(PRICES_QUERY)
+                            .bind(blockspan.low)
+                            .bind(blockspan.high)
+                            .fetch(pool)
+                    },
+                )
+                .await;
+        }
+
+        let blockspan = span.blockspan(pool, &span.timespan).await?;
+        let previous: Option<Price> = sqlx::query_as(PREVIOUS_PRICE_QUERY)
+            .bind(blockspan.low)
+            .fetch_optional(pool)
+            .await?;
+        let prices: Vec<Price> = sqlx::query_as(PRICES_QUERY)
+            .bind(blockspan.low)
+            .bind(blockspan.high)
+            .fetch_all(pool)
+            .await?;
+        // Prices after the latest ingested block are not known yet
+        let tip = ChainBounds::fetch(pool).await?.tip_timestamp;
+        let timespan = TimeSpan::for_date_range(span.timespan.low, span.timespan.high.min(tip));
+        let days = daily_prices(&opts.tz(), &timespan, previous.map(|p| p.price), &prices);
+
+        let rows = days.into_iter().map(Ok::<_, Error>);
+        format
+            .output(
+                opts.output("oracle-prices", &format)?,
+                stream::iter(rows).boxed(),
+            )
+            .await
+    }
+}
+
+/// Aggregates the given prices per day (in the given timezone) of the given
+/// timespan. Days before the first known price are left out.
+fn daily_prices(
+    tz: &Tz,
+    timespan: &TimeSpan,
+    mut current: Option<f64>,
+    prices: &[Price],
+) -> Vec<DailyPrice> {
+    let mut prices = prices.iter().peekable();
+    let mut days = Vec::new();
+    for day in timespan.partition(Partition::Day, tz) {
+        let mut ohlc = current.map(|price| (price, price, price, price));
+        let mut count = 0;
+        let mut weighted = 0.0;
+        let mut duration = 0;
+        let mut since = day.low;
+        while let Some(price) = prices.next_if(|price| price.timestamp < day.high) {
+            if let Some(current) = current {
+                let elapsed = (price.timestamp.max(since) - since).num_milliseconds();
+                weighted += current * elapsed as f64;
+                duration += elapsed;
+            }
+            since = price.timestamp.max(since);
+            current = Some(price.price);
+            count += 1;
+            ohlc = Some(match ohlc {
+                None => (price.price, price.price, price.price, price.price),
+                Some((open, high, low, _)) => (
+                    open,
+                    high.max(price.price),
+                    low.min(price.price),
+                    price.price,
+                ),
+            });
+        }
+        let Some((open, high, low, close)) = ohlc else {
+            continue;
+        };
+        let elapsed = (day.high - since).num_milliseconds();
+        weighted += close * elapsed as f64;
+        duration += elapsed;
+        days.push(DailyPrice {
+            date: day.low.with_timezone(tz).date_naive(),
+            open,
+            high,
+            low,
+            close,
+            twap: if duration > 0 {
+                weighted / duration as f64
+            } else {
+                close
+            },
+            count,
+        });
+    }
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn price(block: i64, timestamp: &str, price: f64) -> Price {
+        Price {
+            block,
+            timestamp: utc(timestamp),
+            price,
+        }
+    }
+
+    fn days(days: &[DailyPrice]) -> Vec<(String, [f64; 5], i64)> {
+        days.iter()
+            .map(|day| {
+                let prices = [day.open, day.high, day.low, day.close, day.twap];
+                (day.date.to_string(), prices, day.count)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn carries_in_the_previous_price() {
+        let timespan =
+            TimeSpan::for_date_range(utc("2023-01-01T00:00:00Z"), utc("2023-01-04T00:00:00Z"));
+        let prices = [price(10, "2023-01-02T12:00:00Z", 4.0)];
+        let daily = daily_prices(&Tz::UTC, &timespan, Some(2.0), &prices);
+        assert_eq!(
+            days(&daily),
+            vec![
+                ("2023-01-01".into(), [2.0, 2.0, 2.0, 2.0, 2.0], 0),
+                ("2023-01-02".into(), [2.0, 4.0, 2.0, 4.0, 3.0], 1),
+                ("2023-01-03".into(), [4.0, 4.0, 4.0, 4.0, 4.0], 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_out_days_before_the_first_price() {
+        let timespan =
+            TimeSpan::for_date_range(utc("2023-01-01T00:00:00Z"), utc("2023-01-03T00:00:00Z"));
+        let prices = [
+            price(10, "2023-01-02T06:00:00Z", 3.0),
+            price(20, "2023-01-02T12:00:00Z", 1.0),
+        ];
+        let daily = daily_prices(&Tz::UTC, &timespan, None, &prices);
+        // Weighted over the 18 hours from the first price only
+        assert_eq!(
+            days(&daily),
+            vec![("2023-01-02".into(), [3.0, 3.0, 1.0, 1.0, 5.0 / 3.0], 2)]
+        );
+        assert!(daily_prices(&Tz::UTC, &timespan, None, &[]).is_empty());
+    }
+
+    #[test]
+    fn clips_the_first_and_last_day() {
+        let timespan =
+            TimeSpan::for_date_range(utc("2023-01-01T12:00:00Z"), utc("2023-01-02T06:00:00Z"));
+        let prices = [price(10, "2023-01-01T18:00:00Z", 4.0)];
+        let daily = daily_prices(&Tz::UTC, &timespan, Some(2.0), &prices);
+        assert_eq!(
+            days(&daily),
+            vec![
+                ("2023-01-01".into(), [2.0, 4.0, 2.0, 4.0, 3.0], 1),
+                ("2023-01-02".into(), [4.0, 4.0, 4.0, 4.0, 4.0], 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn groups_days_in_the_timezone() {
+        let timespan =
+            TimeSpan::for_date_range(utc("2023-01-01T05:00:00Z"), utc("2023-01-03T05:00:00Z"));
+        let prices = [price(10, "2023-01-01T23:00:00Z", 4.0)];
+        let tz = chrono_tz::America::New_York;
+        let daily = daily_prices(&tz, &timespan, Some(2.0), &prices);
+        assert_eq!(
+            days(&daily),
+            vec![
+                ("2023-01-01".into(), [2.0, 4.0, 2.0, 4.0, 2.5], 1),
+                ("2023-01-02".into(), [4.0, 4.0, 4.0, 4.0, 4.0], 0),
+            ]
+        );
+    }
+}
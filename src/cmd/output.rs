@@ -0,0 +1,140 @@
+use crate::Result;
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use tempfile::TempPath;
+
+/// The destination of a command's output. Output is either written straight
+/// to stdout or to a temporary file next to the target path which is moved
+/// into place by [`Output::finish`]. Files with a `.gz` or `.zst` extension
+/// are compressed accordingly.
+pub struct Output {
+    writer: Writer,
+    target: Option<Target>,
+    appending: bool,
+}
+
+enum Target {
+    /// The temporary file replaces the file at the path
+    Replace(TempPath, PathBuf),
+    /// The contents of the temporary file are appended to the file at the path
+    Append(TempPath, PathBuf),
+}
+
+enum Writer {
+    Stdout(io::Stdout),
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    pub fn stdout() -> Self {
+        Self {
+            writer: Writer::Stdout(io::stdout()),
+            target: None,
+            appending: false,
+        }
+    }
+
+    pub fn create(path: &Path) -> Result<Self> {
+        Self::open(path, false)
+    }
+
+    /// Like [`Output::create`] but appends the written data to the existing
+    /// file at the given path once the output is finished. Compressed files
+    /// get a new compressed member appended. The append itself is not atomic,
+    /// a crash while finishing can leave part of the data appended.
+    pub fn append(path: &Path) -> Result<Self> {
+        Self::open(path, true)
+    }
+
+    /// Whether the output is being appended to existing, non-empty data
+    pub fn is_append(&self) -> bool {
+        self.appending
+    }
+
+    fn open(path: &Path, append: bool) -> Result<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut builder = tempfile::Builder::new();
+        #[cfg(unix)]
+        {
+            // Temporary files are private by default, the final output should
+            // not be.
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o644));
+        }
+        let (file, temp_path) = builder.tempfile_in(dir)?.into_parts();
+        let appending = append && path.metadata().is_ok_and(|meta| meta.len() > 0);
+        let file = BufWriter::new(file);
+        let writer = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Writer::Gzip(GzEncoder::new(file, Compression::default())),
+            Some("zst" | "zstd") => Writer::Zstd(zstd::Encoder::new(file, 0)?),
+            _ => Writer::Plain(file),
+        };
+        Ok(Self {
+            writer,
+            target: Some(match append {
+                true => Target::Append(temp_path, path.to_path_buf()),
+                false => Target::Replace(temp_path, path.to_path_buf()),
+            }),
+            appending,
+        })
+    }
+
+    /// Flushes all written data and, when writing to a file, atomically moves
+    /// the file to its target path or appends it to the target. Dropping an
+    /// output without finishing it discards anything written to a file.
+    pub fn finish(self) -> Result {
+        let file = match self.writer {
+            Writer::Stdout(mut stdout) => {
+                stdout.flush()?;
+                None
+            }
+            Writer::Plain(file) => Some(file),
+            Writer::Gzip(encoder) => Some(encoder.finish()?),
+            Writer::Zstd(encoder) => Some(encoder.finish()?),
+        };
+        if let Some(file) = file {
+            file.into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()?;
+        }
+        match self.target {
+            None => (),
+            Some(Target::Replace(temp_path, path)) => temp_path.persist(path)?,
+            Some(Target::Append(temp_path, path)) => {
+                let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+                io::copy(&mut File::open(&temp_path)?, &mut file)?;
+                file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.writer {
+            Writer::Stdout(writer) => writer.write(buf),
+            Writer::Plain(writer) => writer.write(buf),
+            Writer::Gzip(writer) => writer.write(buf),
+            Writer::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.writer {
+            Writer::Stdout(writer) => writer.flush(),
+            Writer::Plain(writer) => writer.flush(),
+            Writer::Gzip(writer) => writer.flush(),
+            Writer::Zstd(writer) => writer.flush(),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::Result;
+use arrow_json::ReaderBuilder;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use futures::stream::{BoxStream, TryStreamExt};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::sync::Arc;
+
+/// Number of rows that are collected from the row stream before they are
+/// encoded as an Arrow record batch.
+const BATCH_SIZE: usize = 8192;
+
+/// Maximum number of rows in a single parquet row group.
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Describes the Arrow schema of a row type that is written out by
+/// [`Format::output`](super::Format::output). Field names must match the
+/// serialized field names of the row type.
+pub trait ArrowSchema {
+    fn schema() -> Schema;
+}
+
+/// A UTC timestamp field as serialized by `chrono`.
+pub fn timestamp_field(name: &str, nullable: bool) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Microsecond, Some("+00:00".into())),
+        nullable,
+    )
+}
+
+pub async fn output<'a, W, E, Er>(
+    output: W,
+    mut rows: BoxStream<'a, std::result::Result<E, Er>>,
+) -> Result
+where
+    W: std::io::Write + Send,
+    E: serde::Serialize + ArrowSchema,
+    crate::Error: From<Er>,
+{
+    let schema = Arc::new(E::schema());
+    let mut decoder = ReaderBuilder::new(schema.clone())
+        .with_batch_size(BATCH_SIZE)
+        .build_decoder()?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(ROW_GROUP_SIZE)
+        .build();
+    let mut writer = ArrowWriter::try_new(output, schema, Some(props))?;
+
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        let row = rows.try_next().await?;
+        let done = row.is_none();
+        batch.extend(row);
+        if batch.len() == BATCH_SIZE || (done && !batch.is_empty()) {
+            decoder.serialize(&batch)?;
+            if let Some(record_batch) = decoder.flush()? {
+                writer.write(&record_batch)?;
+            }
+            batch.clear();
+        }
+        if done {
+            break;
+        }
+    }
+    writer.close()?;
+    Ok(())
+}
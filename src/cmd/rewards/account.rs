@@ -1,28 +1,33 @@
 use crate::{
-    cmd::{Format, Opts},
-    BlockSpan, Result,
+    cmd::{
+        parquet::{timestamp_field, ArrowSchema},
+        Format, FxArgs, Opts, RangeArgs,
+    },
+    Error, Result,
 };
+use arrow_schema::{DataType, Field, Schema};
 use chrono::{DateTime, NaiveDate, Utc};
+use futures::{StreamExt, TryStreamExt};
 use sqlx::postgres::PgPool;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Generates CSV  or JSON output with HNT rewards for all reward entries for
-/// validators and emitted securities owned by a given wallet.  
+/// Generates output in any --format with HNT rewards for all reward entries for
+/// validators and emitted securities owned by a given wallet.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 pub struct Cmd {
     /// The wallet address to look up validators for
     account: String,
 
-    /// The start day (inclusive) to run the report over (in UTC). The start
-    /// time is at the beginning midnight of the given date (00:00:00).
-    start: NaiveDate,
+    #[structopt(flatten)]
+    range: RangeArgs,
 
-    /// The end day (exclusive) to run the report over (in UTC). The end time is
-    /// at the beginning midnight of the given date (00:00:00).
-    end: NaiveDate,
+    #[structopt(flatten)]
+    fx: FxArgs,
 
-    #[structopt(long, default_value)]
-    format: Format,
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
 }
 
 #[derive(Debug, serde::Serialize, sqlx::FromRow)]
@@ -35,6 +40,40 @@ pub struct ValidatorReward {
     hnt: f64,
     usd_oracle_price: f64,
     usd_amount: f64,
+    /// The currency given by --currency
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<String>,
+    /// The date of the exchange rate, the day of the reward
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fx_date: Option<NaiveDate>,
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fx_rate: Option<f64>,
+    /// The usd_amount converted to the currency
+    #[sqlx(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amount: Option<f64>,
+}
+
+impl ArrowSchema for ValidatorReward {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("block", DataType::Int64, false),
+            timestamp_field("timestamp", false),
+            Field::new("reward_type", DataType::Utf8, false),
+            Field::new("transaction_hash", DataType::Utf8, false),
+            Field::new("validator", DataType::Utf8, true),
+            Field::new("hnt", DataType::Float64, false),
+            Field::new("usd_oracle_price", DataType::Float64, false),
+            Field::new("usd_amount", DataType::Float64, false),
+            Field::new("currency", DataType::Utf8, true),
+            Field::new("fx_date", DataType::Date32, true),
+            Field::new("fx_rate", DataType::Float64, true),
+            Field::new("amount", DataType::Float64, true),
+        ])
+    }
 }
 
 const VALIDATOR_REWRDS_QUERY: &str = r#"
@@ -55,14 +94,42 @@ const VALIDATOR_REWRDS_QUERY: &str = r#"
 "#;
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let blockspan = BlockSpan::for_date_range(pool, self.start, self.end).await?;
-        let rows = sqlx::query_as::<_, ValidatorReward>(VALIDATOR_REWRDS_QUERY)
-            .bind(blockspan.low)
-            .bind(blockspan.high)
-            .bind(&self.account)
-            .fetch(pool);
-        self.format.output(std::io::stdout(), rows).await?;
-        Ok(())
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.range.resolve(pool, &opts).await?;
+        let fx = self.fx.load()?;
+        let tz = opts.tz();
+        let key = match &fx {
+            Some(fx) => format!("rewards-account {} {}", self.account, fx.currency()),
+            None => format!("rewards-account {}", self.account),
+        };
+        opts.output_incremental(
+            pool,
+            "rewards-account",
+            &key,
+            &format,
+            &span,
+            |_, blockspan| {
+                sqlx::query_as::<_, ValidatorReward>(VALIDATOR_REWRDS_QUERY)
+                    .bind(blockspan.low)
+                    .bind(blockspan.high)
+                    .bind(&self.account)
+                    .fetch(pool)
+                    .map_err(Error::from)
+                    .and_then(|mut reward| async {
+                        if let Some(fx) = &fx {
+                            let date = reward.timestamp.with_timezone(&tz).date_naive();
+                            let rate = fx.rate(date)?;
+                            reward.currency = Some(fx.currency().to_string());
+                            reward.fx_date = Some(date);
+                            reward.fx_rate = Some(rate);
+                            reward.amount = Some(reward.usd_amount * rate);
+                        }
+                        Ok(reward)
+                    })
+                    .boxed()
+            },
+        )
+        .await
     }
 }
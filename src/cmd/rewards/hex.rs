@@ -1,21 +1,23 @@
-use crate::{cmd::Opts, BlockSpan, Result};
-use chrono::NaiveDate;
-use futures::TryStreamExt;
-use serde::{ser::SerializeSeq, Serializer};
+use crate::{
+    cmd::{parquet::ArrowSchema, DateArgs, Format, Opts},
+    Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::stream::{StreamExt, TryStreamExt};
 use sqlx::postgres::PgPool;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Generates JSON output with rewards for each res8 hex that has hotspots.  
+/// Generates output in any --format with rewards for each res8 hex that has
+/// hotspots.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 pub struct Cmd {
-    /// The day to run the report over (in UTC). The start time is at the
-    /// beginning midnight of the given date (00:00:00).
-    date: NaiveDate,
+    #[structopt(flatten)]
+    date: DateArgs,
 
-    /// The number of days to include in the timespan. Days can be positive or
-    /// negative.
-    #[structopt(default_value = "-1")]
-    days: i64,
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
 }
 
 #[derive(Debug, serde::Serialize, sqlx::FromRow)]
@@ -28,6 +30,17 @@ pub struct HexReward {
     avg: Option<f64>,
 }
 
+impl ArrowSchema for HexReward {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("hex", DataType::Utf8, false),
+            Field::new("amount", DataType::Float64, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("avg", DataType::Float64, true),
+        ])
+    }
+}
+
 const HEXREWARDS_QUERY: &str = r#"
     with stats as (
         select r.gateway, sum(r.amount) as amount
@@ -48,22 +61,29 @@ const HEXREWARDS_QUERY: &str = r#"
 "#;
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let blockspan = BlockSpan::from_date(pool, self.date, self.days).await?;
-        let mut rows = sqlx::query_as::<_, HexReward>(HEXREWARDS_QUERY)
-            .bind(blockspan.low)
-            .bind(blockspan.high)
-            .fetch(pool);
-        let mut serializer = serde_json::Serializer::pretty(std::io::stdout());
-        let mut entries = serializer.serialize_seq(None)?;
-        let abs_days = self.days.abs();
-        while let Some(mut reward) = rows.try_next().await? {
-            if abs_days > 1 {
-                reward.avg = Some(reward.amount / abs_days as f64)
-            }
-            entries.serialize_element(&reward)?;
-        }
-        entries.end()?;
-        Ok(())
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.date.resolve(pool, &opts, -1).await?;
+        opts.output_timespan(
+            pool,
+            "rewards-hex",
+            &format,
+            &span,
+            |timespan, blockspan| {
+                let days = timespan.days();
+                sqlx::query_as::<_, HexReward>(HEXREWARDS_QUERY)
+                    .bind(blockspan.low)
+                    .bind(blockspan.high)
+                    .fetch(pool)
+                    .map_ok(move |mut reward| {
+                        if days > 1 {
+                            reward.avg = Some(reward.amount / days as f64)
+                        }
+                        reward
+                    })
+                    .boxed()
+            },
+        )
+        .await
     }
 }
@@ -1,20 +1,27 @@
-use crate::{cmd::Opts, BlockSpan, Result};
-use chrono::NaiveDate;
+use crate::{
+    cmd::{parquet::ArrowSchema, print_json, DateArgs, Format, Opts},
+    BlockSpan, Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::stream::{self, StreamExt};
 use serde_json::json;
 use sqlx::postgres::PgPool;
+use std::result::Result as StdResult;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Generates JSON output with rewards for each res8 hex that has hotspots.  
+/// Generates output in any --format with a summary of the network rewards over
+/// a given timespan. JSON output is a single object with the reward statistics
+/// nested under "rewards" and can not be partitioned, the other formats have a
+/// flat row per timespan.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 pub struct Cmd {
-    /// The day to run the report over (in UTC). The start time is at the
-    /// beginning midnight of the given date (00:00:00).
-    date: NaiveDate,
+    #[structopt(flatten)]
+    date: DateArgs,
 
-    /// The number of days to include in the timespan. Days can be positive or
-    /// negative.
-    #[structopt(default_value = "-1")]
-    days: i64,
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
 }
 
 #[derive(Debug, serde::Serialize, sqlx::FromRow)]
@@ -26,6 +33,54 @@ pub struct NetworkRewards {
     stddev: f64,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct NetworkSummary {
+    securities_percent: f64,
+    consensus_percent: f64,
+    hotspots_online: i64,
+    hotspot_avg_rewards: f64,
+    rewards_min: f64,
+    rewards_max: f64,
+    rewards_total: f64,
+    rewards_median: f64,
+    rewards_stddev: f64,
+}
+
+impl NetworkSummary {
+    /// The summary in the JSON shape with nested reward statistics
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "securities_percent": self.securities_percent,
+            "consensus_percent": self.consensus_percent,
+            "hotspots_online": self.hotspots_online,
+            "hotspot_avg_rewards": self.hotspot_avg_rewards,
+            "rewards": {
+                "min": self.rewards_min,
+                "max": self.rewards_max,
+                "total": self.rewards_total,
+                "median": self.rewards_median,
+                "stddev": self.rewards_stddev,
+            },
+        })
+    }
+}
+
+impl ArrowSchema for NetworkSummary {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("securities_percent", DataType::Float64, false),
+            Field::new("consensus_percent", DataType::Float64, false),
+            Field::new("hotspots_online", DataType::Int64, false),
+            Field::new("hotspot_avg_rewards", DataType::Float64, false),
+            Field::new("rewards_min", DataType::Float64, false),
+            Field::new("rewards_max", DataType::Float64, false),
+            Field::new("rewards_total", DataType::Float64, false),
+            Field::new("rewards_median", DataType::Float64, false),
+            Field::new("rewards_stddev", DataType::Float64, false),
+        ])
+    }
+}
+
 const REWARDS_QUERY: &str = r#"
     with reward_data as (
         select
@@ -60,39 +115,65 @@ const GET_VAR: &str = r#"
 "#;
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let blockspan = BlockSpan::from_date(pool, self.date, self.days).await?;
-        let rewards = sqlx::query_as::<_, NetworkRewards>(REWARDS_QUERY)
-            .bind(blockspan.low)
-            .bind(blockspan.high)
-            .fetch_one(pool)
-            .await?;
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.date.resolve(pool, &opts, -1).await?;
+        if let Format::Json = format {
+            let timespan = &span.timespan;
+            let blockspan = span.blockspan(pool, timespan).await?;
+            let summary = fetch_summary(pool, timespan.days().max(1), blockspan).await?;
+            return print_json(opts.output("rewards-network", &format)?, &summary.to_json());
+        }
+        opts.output_timespan(
+            pool,
+            "rewards-network",
+            &format,
+            &span,
+            |timespan, blockspan| {
+                stream::once(fetch_summary(pool, timespan.days().max(1), blockspan)).boxed()
+            },
+        )
+        .await
+    }
+}
 
-        let (hotspots_online,): (i64,) = sqlx::query_as(HOTSPOTS_ONLINE).fetch_one(pool).await?;
-        let (securities_percent,): (f64,) = sqlx::query_as(GET_VAR)
-            .bind("securities_percent")
-            .fetch_one(pool)
-            .await?;
-        let (consensus_percent,): (f64,) = sqlx::query_as(GET_VAR)
-            .bind("consensus_percent")
-            .fetch_one(pool)
-            .await?;
-        let (poc_challengers_percent,): (f64,) = sqlx::query_as(GET_VAR)
-            .bind("poc_challengers_percent")
-            .fetch_one(pool)
-            .await?;
-        let hotspot_avg_rewards = (rewards.total / self.days.abs() as f64)
-            * (1.0 - consensus_percent - securities_percent - poc_challengers_percent)
-            / hotspots_online as f64;
+async fn fetch_summary(
+    pool: &PgPool,
+    days: i64,
+    blockspan: BlockSpan,
+) -> StdResult<NetworkSummary, sqlx::Error> {
+    let rewards = sqlx::query_as::<_, NetworkRewards>(REWARDS_QUERY)
+        .bind(blockspan.low)
+        .bind(blockspan.high)
+        .fetch_one(pool)
+        .await?;
 
-        let summary = json!({
-            "securities_percent": securities_percent,
-            "consensus_percent": consensus_percent,
-            "hotspots_online": hotspots_online,
-            "hotspot_avg_rewards": hotspot_avg_rewards,
-            "rewards": rewards,
-        });
-        println!("{}", serde_json::to_string_pretty(&summary)?);
-        Ok(())
-    }
+    let (hotspots_online,): (i64,) = sqlx::query_as(HOTSPOTS_ONLINE).fetch_one(pool).await?;
+    let (securities_percent,): (f64,) = sqlx::query_as(GET_VAR)
+        .bind("securities_percent")
+        .fetch_one(pool)
+        .await?;
+    let (consensus_percent,): (f64,) = sqlx::query_as(GET_VAR)
+        .bind("consensus_percent")
+        .fetch_one(pool)
+        .await?;
+    let (poc_challengers_percent,): (f64,) = sqlx::query_as(GET_VAR)
+        .bind("poc_challengers_percent")
+        .fetch_one(pool)
+        .await?;
+    let hotspot_avg_rewards = (rewards.total / days as f64)
+        * (1.0 - consensus_percent - securities_percent - poc_challengers_percent)
+        / hotspots_online as f64;
+
+    Ok(NetworkSummary {
+        securities_percent,
+        consensus_percent,
+        hotspots_online,
+        hotspot_avg_rewards,
+        rewards_min: rewards.min,
+        rewards_max: rewards.max,
+        rewards_total: rewards.total,
+        rewards_median: rewards.median,
+        rewards_stddev: rewards.stddev,
+    })
 }
@@ -0,0 +1,268 @@
+use crate::{cmd::Opts, timespan::date_series, BlockSpan, Moment, Period, Result, TimeSpan};
+use anyhow::bail;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use structopt::StructOpt;
+
+// Options that give the timespan of a command as a pair of timestamps or block
+// heights in place of its positional arguments. This is not a doc comment
+// since it would replace the about text of the commands it is flattened into.
+#[derive(Debug, StructOpt)]
+pub struct SpanArgs {
+    /// The start (inclusive) of the timespan as a date, RFC3339 timestamp or
+    /// date expression, e.g. 2023-04-01T14:00:00Z or last-month. Replaces the
+    /// positional date arguments. Without --end a date expression covers its
+    /// whole extent.
+    #[structopt(long = "start")]
+    start_time: Option<Moment>,
+
+    /// The end (exclusive) of the timespan as a date, RFC3339 timestamp or date
+    /// expression
+    #[structopt(long = "end", requires = "start-time")]
+    end_time: Option<Moment>,
+
+    /// The first block (inclusive) to run over. Replaces the positional date
+    /// arguments and --start/--end.
+    #[structopt(long, requires = "to-block", conflicts_with = "start-time")]
+    from_block: Option<i64>,
+
+    /// The last block (inclusive) to run over
+    #[structopt(long, requires = "from-block")]
+    to_block: Option<i64>,
+}
+
+/// A resolved timespan, optionally pinned to an exact range of blocks
+pub struct Span {
+    pub timespan: TimeSpan,
+    blocks: Option<BlockSpan>,
+}
+
+impl Span {
+    /// Looks up the block span for the given timespan, which is this span or
+    /// a part of it. When the span is pinned to a range of blocks the result
+    /// is limited to those blocks.
+    pub async fn blockspan(&self, pool: &PgPool, timespan: &TimeSpan) -> Result<BlockSpan> {
+        match &self.blocks {
+            Some(blocks) if timespan == &self.timespan => Ok(blocks.clone()),
+            Some(blocks) => Ok(BlockSpan::for_timespan(pool, timespan).await?.clip(blocks)),
+            None => Ok(BlockSpan::for_timespan(pool, timespan).await?),
+        }
+    }
+}
+
+impl From<TimeSpan> for Span {
+    fn from(timespan: TimeSpan) -> Self {
+        Self {
+            timespan,
+            blocks: None,
+        }
+    }
+}
+
+impl SpanArgs {
+    /// The span given by --from-block and --to-block, if any
+    async fn blocks(&self, pool: &PgPool) -> Result<Option<Span>> {
+        let (Some(low), Some(high)) = (self.from_block, self.to_block) else {
+            return Ok(None);
+        };
+        if low > high {
+            bail!("--from-block {low} is above --to-block {high}");
+        }
+        let blocks = BlockSpan { low, high };
+        Ok(Some(Span {
+            timespan: blocks.timespan(pool).await?,
+            blocks: Some(blocks),
+        }))
+    }
+
+    /// The timespan given by --start and --end, if any
+    fn range(&self, opts: &Opts) -> Result<Option<TimeSpan>> {
+        match &self.start_time {
+            None => Ok(None),
+            Some(start) => resolve_range(opts, start, self.end_time.as_ref()).map(Some),
+        }
+    }
+
+    /// Resolves the span from --from-block and --to-block or --start and
+    /// --end, or else from the given positional date and length. Without a
+    /// length the timespan covers the extent of a date expression, or the
+    /// given default number of days.
+    async fn resolve(
+        &self,
+        pool: &PgPool,
+        opts: &Opts,
+        date: Option<&Moment>,
+        length: Option<&Period>,
+        default_days: i64,
+    ) -> Result<Span> {
+        if let Some(span) = self.blocks(pool).await? {
+            if date.is_some() {
+                bail!("--from-block and --to-block can not be combined with a date");
+            }
+            opts.ensure_fresh(pool, span.timespan.high).await?;
+            return Ok(span);
+        }
+        let timespan = match (self.range(opts)?, date) {
+            (Some(_), Some(_)) => bail!("--start and --end can not be combined with a date"),
+            (Some(timespan), None) => timespan,
+            (None, Some(date)) => {
+                let length = length
+                    .cloned()
+                    .or_else(|| date.extent())
+                    .unwrap_or_else(|| Period::days(default_days));
+                TimeSpan::for_period(date.resolve(&opts.tz())?, &length)?
+            }
+            (None, None) => bail!("a date, --start or --from-block are required"),
+        };
+        opts.ensure_fresh(pool, timespan.high).await?;
+        Ok(timespan.into())
+    }
+
+    /// Resolves the span from --from-block and --to-block or --start and
+    /// --end, or else from the given positional start and end.
+    async fn resolve_range(
+        &self,
+        pool: &PgPool,
+        opts: &Opts,
+        start: Option<&Moment>,
+        end: Option<&Moment>,
+    ) -> Result<Span> {
+        if let Some(span) = self.blocks(pool).await? {
+            if start.is_some() {
+                bail!("--from-block and --to-block can not be combined with positional dates");
+            }
+            opts.ensure_fresh(pool, span.timespan.high).await?;
+            return Ok(span);
+        }
+        let timespan = match (self.range(opts)?, start) {
+            (Some(_), Some(_)) => {
+                bail!("--start and --end can not be combined with positional dates")
+            }
+            (Some(timespan), None) => timespan,
+            (None, Some(start)) => resolve_range(opts, start, end)?,
+            (None, None) => bail!("a start date, --start or --from-block are required"),
+        };
+        opts.ensure_fresh(pool, timespan.high).await?;
+        Ok(timespan.into())
+    }
+}
+
+// The positional date and length of a report over a timespan, along with the
+// options that replace them. Not a doc comment, see SpanArgs.
+#[derive(Debug, StructOpt)]
+pub struct DateArgs {
+    /// The day, RFC3339 timestamp or date expression like yesterday, -7d,
+    /// last-month, 2023-Q2 or 2023-W14 to start the timespan at. A day starts
+    /// at midnight in the --tz timezone.
+    #[structopt(required_unless_one = &["start-time", "from-block"])]
+    date: Option<Moment>,
+
+    /// The length of the timespan as a number of days or an ISO-8601 duration
+    /// such as PT6H or P1W. The length can be positive or negative. A date
+    /// expression defaults to its extent, other dates to the day after them,
+    /// or the day before them for reward reports.
+    length: Option<Period>,
+
+    #[structopt(flatten)]
+    span: SpanArgs,
+}
+
+impl DateArgs {
+    /// Resolves the span from the positional date and length or the options
+    /// that replace them. Without a length a plain date covers the given
+    /// default number of days.
+    pub async fn resolve(&self, pool: &PgPool, opts: &Opts, default_days: i64) -> Result<Span> {
+        self.span
+            .resolve(
+                pool,
+                opts,
+                self.date.as_ref(),
+                self.length.as_ref(),
+                default_days,
+            )
+            .await
+    }
+}
+
+// The positional start and end of a report over a range of dates, along with
+// the options that replace them. Not a doc comment, see SpanArgs.
+#[derive(Debug, StructOpt)]
+pub struct RangeArgs {
+    /// The start day, RFC3339 timestamp or date expression (inclusive) to run
+    /// the report over (in the --tz timezone). A day starts at the beginning
+    /// midnight of the given date (00:00:00). Date expressions like
+    /// yesterday, -7d, last-month, 2023-Q2 or 2023-W14 cover their whole
+    /// extent when no end is given.
+    #[structopt(required_unless_one = &["start-time", "from-block"])]
+    start: Option<Moment>,
+
+    /// The end day, RFC3339 timestamp or date expression (exclusive) to run
+    /// the report over (in the --tz timezone)
+    end: Option<Moment>,
+
+    #[structopt(flatten)]
+    span: SpanArgs,
+}
+
+impl RangeArgs {
+    /// Resolves the span from the positional start and end or the options
+    /// that replace them
+    pub async fn resolve(&self, pool: &PgPool, opts: &Opts) -> Result<Span> {
+        self.span
+            .resolve_range(pool, opts, self.start.as_ref(), self.end.as_ref())
+            .await
+    }
+}
+
+// Options that generate a series of dates in place of a list of positional
+// dates. Not a doc comment for the same reason as above.
+#[derive(Debug, StructOpt)]
+pub struct SeriesArgs {
+    /// The first date of a series of dates to report on, as a date or date
+    /// expression. Replaces the positional dates.
+    #[structopt(long, requires = "to")]
+    from: Option<Moment>,
+
+    /// The last date (inclusive) of the series
+    #[structopt(long, requires = "from")]
+    to: Option<Moment>,
+
+    /// The interval between the dates of the series as a number of days or an
+    /// ISO-8601 duration like P1W or P1M
+    #[structopt(long, default_value = "1")]
+    step: Period,
+}
+
+impl SeriesArgs {
+    /// The series of dates given by --from, --to and --step, or else the given
+    /// positional dates.
+    pub fn dates(&self, opts: &Opts, dates: &[NaiveDate]) -> Result<Vec<NaiveDate>> {
+        let (Some(from), Some(to)) = (&self.from, &self.to) else {
+            if dates.is_empty() {
+                bail!("one or more dates or --from and --to are required");
+            }
+            return Ok(dates.to_vec());
+        };
+        if !dates.is_empty() {
+            bail!("--from and --to can not be combined with dates");
+        }
+        let tz = opts.tz();
+        let from = from.resolve(&tz)?.date_naive();
+        let to = to.resolve(&tz)?.date_naive();
+        date_series(from, to, &self.step)
+    }
+}
+
+/// The timespan from the given start to the given end, or over the extent of
+/// the start when there is no end.
+fn resolve_range(opts: &Opts, start: &Moment, end: Option<&Moment>) -> Result<TimeSpan> {
+    let tz = opts.tz();
+    match (end, start.extent()) {
+        (Some(end), _) => Ok(TimeSpan::for_date_range(
+            start.resolve(&tz)?,
+            end.resolve(&tz)?,
+        )),
+        (None, Some(extent)) => TimeSpan::for_period(start.resolve(&tz)?, &extent),
+        (None, None) => bail!("an end date is required unless the start is a date expression"),
+    }
+}
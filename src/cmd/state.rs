@@ -0,0 +1,55 @@
+use crate::{cmd::Output, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+/// Checkpoints of incremental extractions, keyed by command, arguments and
+/// output path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    checkpoints: BTreeMap<String, Checkpoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The highest block that has been exported
+    pub block: i64,
+    pub updated: DateTime<Utc>,
+}
+
+impl State {
+    /// Loads the state file at the given path. A missing file is treated as an
+    /// empty state.
+    pub fn load(path: &Path) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result {
+        let mut output = Output::create(path)?;
+        serde_json::to_writer_pretty(&mut output, self)?;
+        output.finish()
+    }
+
+    pub fn checkpoint(&self, key: &str) -> Option<&Checkpoint> {
+        self.checkpoints.get(key)
+    }
+
+    pub fn set_checkpoint(&mut self, key: &str, block: i64) {
+        self.checkpoints.insert(
+            key.to_string(),
+            Checkpoint {
+                block,
+                updated: Utc::now(),
+            },
+        );
+    }
+}
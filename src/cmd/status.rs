@@ -0,0 +1,44 @@
+use crate::{
+    cmd::{format_duration, print_json, Format, Opts},
+    ChainBounds, Result,
+};
+use anyhow::bail;
+use chrono::Utc;
+use serde_json::json;
+use sqlx::postgres::PgPool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Shows the first and latest block ingested by the etl and how far the latest
+/// block lags behind the current time. Exits with an error when the lag is
+/// more than --require-fresh.
+pub struct Cmd {}
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let bounds = ChainBounds::fetch(pool).await?;
+        let lag = Utc::now() - bounds.tip_timestamp;
+        let json = json!({
+            "genesis": {
+                "height": bounds.genesis_height,
+                "timestamp": bounds.genesis_timestamp,
+            },
+            "tip": {
+                "height": bounds.tip_height,
+                "timestamp": bounds.tip_timestamp,
+            },
+            "lag": format_duration(lag),
+            "lag_seconds": lag.num_seconds(),
+        });
+        print_json(opts.output("status", &Format::Json)?, &json)?;
+        if let Some(limit) = opts.require_fresh {
+            if lag.to_std().unwrap_or_default() > limit.into() {
+                bail!(
+                    "the etl lags {} behind, more than --require-fresh {limit}",
+                    format_duration(lag)
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,62 +1,181 @@
 use crate::{
-    cmd::{Format, Opts},
-    BlockSpan, Result,
+    cmd::{parquet::ArrowSchema, Format, Opts, SeriesArgs},
+    BlockSpanError, ChainBounds, Error, Result,
 };
-use chrono::NaiveDate;
+use arrow_schema::{DataType, Field, Schema};
+use chrono::{DateTime, NaiveDate, Utc};
 use futures::stream::{self, StreamExt, TryStreamExt};
 use sqlx::postgres::PgPool;
-use std::result::Result as StdResult;
+use std::collections::HashMap;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
-/// Gets the running supply at a given date.
+/// Gets the running supply of every token at a given date, or at every date of
+/// a series.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 pub struct Cmd {
-    /// One or more end dates (exclusive) to run the report over (in UTC). The
-    /// end time is at the beginning midnight of the given date (00:00:00).
+    /// One or more end dates (exclusive) to run the report over (in the --tz
+    /// timezone). The end time is at the beginning midnight of the given date
+    /// (00:00:00).
     end: Vec<NaiveDate>,
 
-    #[structopt(long, default_value)]
-    format: Format,
+    #[structopt(flatten)]
+    series: SeriesArgs,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
 }
 
-#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+#[derive(Debug, Clone, serde::Serialize, sqlx::FromRow)]
 pub struct Supply {
     #[sqlx(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     date: Option<NaiveDate>,
     block: i64,
+    /// The liquid HNT, not including staked HNT
     hnt: f64,
+    staked_hnt: f64,
+    /// The liquid and staked HNT
+    total_hnt: f64,
+    hst: f64,
+    mobile: f64,
+    iot: f64,
+    dc: i64,
 }
 
+impl ArrowSchema for Supply {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("date", DataType::Date32, true),
+            Field::new("block", DataType::Int64, false),
+            Field::new("hnt", DataType::Float64, false),
+            Field::new("staked_hnt", DataType::Float64, false),
+            Field::new("total_hnt", DataType::Float64, false),
+            Field::new("hst", DataType::Float64, false),
+            Field::new("mobile", DataType::Float64, false),
+            Field::new("iot", DataType::Float64, false),
+            Field::new("dc", DataType::Int64, false),
+        ])
+    }
+}
+
+/// Computes the supply of every token at each of the given ascending block
+/// heights in one pass. The supply at a height is the sum of the latest
+/// balance of every account at or below it. Every change of an account's
+/// balance is a delta to the supply, the deltas are bucketed by the first
+/// height they count towards and summed up over the heights.
 const SUPPLY_QUERY: &str = r#"
-    with balances as (
-        select address, max(balance) as balance
+    with deltas as (
+        select
+            block,
+            coalesce(balance, 0) - coalesce(lag(balance) over w, 0) as hnt,
+            coalesce(staked_balance, 0) - coalesce(lag(staked_balance) over w, 0) as staked_hnt,
+            coalesce(security_balance, 0) - coalesce(lag(security_balance) over w, 0) as hst,
+            coalesce(mobile_balance, 0) - coalesce(lag(mobile_balance) over w, 0) as mobile,
+            coalesce(iot_balance, 0) - coalesce(lag(iot_balance) over w, 0) as iot,
+            coalesce(dc_balance, 0) - coalesce(lag(dc_balance) over w, 0) as dc
         from accounts
-        where block <= $1 and balance > 0
-        group by address
-    ) 
-    select $1 as block, greatest(0, sum(balance)::float8) / 100000000 as hnt from balances;
+        where block <= $2
+        window w as (partition by address order by block)
+    ),
+    buckets as (
+        select
+            width_bucket(block - 1, $1) + 1 as bucket,
+            sum(hnt) as hnt,
+            sum(staked_hnt) as staked_hnt,
+            sum(hst) as hst,
+            sum(mobile) as mobile,
+            sum(iot) as iot,
+            sum(dc) as dc
+        from deltas
+        group by 1
+    ),
+    supplies as (
+        select
+            h.block,
+            h.bucket,
+            greatest(0, coalesce(sum(b.hnt) over w, 0)) as hnt,
+            greatest(0, coalesce(sum(b.staked_hnt) over w, 0)) as staked_hnt,
+            greatest(0, coalesce(sum(b.hst) over w, 0)) as hst,
+            greatest(0, coalesce(sum(b.mobile) over w, 0)) as mobile,
+            greatest(0, coalesce(sum(b.iot) over w, 0)) as iot,
+            greatest(0, coalesce(sum(b.dc) over w, 0)) as dc
+        from unnest($1) with ordinality as h(block, bucket)
+        left join buckets b on b.bucket = h.bucket
+        window w as (order by h.bucket)
+    )
+    select
+        block,
+        hnt::float8 / 100000000 as hnt,
+        staked_hnt::float8 / 100000000 as staked_hnt,
+        (hnt + staked_hnt)::float8 / 100000000 as total_hnt,
+        hst::float8 / 100000000 as hst,
+        mobile::float8 / 100000000 as mobile,
+        iot::float8 / 100000000 as iot,
+        dc::int8 as dc
+    from supplies
+    order by bucket;
+"#;
+
+/// The latest block at or before each of the given timestamps, in order
+const HEIGHTS_QUERY: &str = r#"
+    select h.height
+    from unnest($1::timestamptz[]) with ordinality as t(timestamp, idx)
+    left join lateral (
+        select height from blocks
+        where timestamp <= t.timestamp
+        order by timestamp desc
+        limit 1
+    ) h on true
+    order by t.idx;
 "#;
 
 impl Cmd {
-    pub async fn run(&self, pool: &PgPool, _opts: Opts) -> Result {
-        let supplies = stream::iter(self.end.clone())
-            .map(|end| Ok(fetch_supply(pool, end)))
-            .try_buffered(10)
-            .boxed();
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let dates = self.series.dates(&opts, &self.end)?;
+        if let Some(end) = dates.iter().max() {
+            let high = opts.start_of_day(*end).with_timezone(&Utc);
+            opts.ensure_fresh(pool, high).await?;
+        }
+        let ends: Vec<DateTime<Utc>> = dates
+            .iter()
+            .map(|date| opts.start_of_day(*date).with_timezone(&Utc))
+            .collect();
+        let heights: Vec<(Option<i64>,)> = sqlx::query_as(HEIGHTS_QUERY)
+            .bind(&ends)
+            .fetch_all(pool)
+            .await?;
+        if let Some(idx) = heights.iter().position(|(height,)| height.is_none()) {
+            let bounds = ChainBounds::fetch(pool).await?;
+            return Err(BlockSpanError::BeforeGenesis {
+                high: ends[idx],
+                genesis: bounds.genesis_timestamp,
+            }
+            .into());
+        }
+        let heights: Vec<i64> = heights.into_iter().filter_map(|(height,)| height).collect();
+
+        let mut blocks = heights.clone();
+        blocks.sort_unstable();
+        blocks.dedup();
+        let supplies: HashMap<i64, Supply> = sqlx::query_as::<_, Supply>(SUPPLY_QUERY)
+            .bind(&blocks)
+            .bind(blocks.last())
+            .fetch(pool)
+            .map_ok(|supply| (supply.block, supply))
+            .try_collect()
+            .await?;
+        let rows = dates.into_iter().zip(heights).map(move |(date, height)| {
+            let mut supply = supplies[&height].clone();
+            supply.date = Some(date);
+            Ok::<_, Error>(supply)
+        });
 
-        self.format.output(std::io::stdout(), supplies).await?;
+        format
+            .output(opts.output("supply", &format)?, stream::iter(rows).boxed())
+            .await?;
         Ok(())
     }
 }
-
-async fn fetch_supply(pool: &PgPool, end: NaiveDate) -> StdResult<Supply, sqlx::Error> {
-    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
-    let blockspan = BlockSpan::for_date_range(pool, epoch, end).await?;
-    let mut supply: Supply = sqlx::query_as::<_, Supply>(SUPPLY_QUERY)
-        .bind(blockspan.high)
-        .fetch_one(pool)
-        .await?;
-    supply.date = Some(end);
-    Ok(supply)
-}
@@ -0,0 +1,489 @@
+use crate::{
+    cmd::{parquet::ArrowSchema, Format, Opts, RangeArgs},
+    Error, Result,
+};
+use anyhow::anyhow;
+use arrow_schema::{DataType, Field, Schema};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures::stream::{self, StreamExt};
+use sqlx::postgres::PgPool;
+use std::collections::{BTreeMap, VecDeque};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Generates output in any --format with the realized gains and losses of a
+/// given wallet in the style of Form 8949. Rewards are the acquired lots,
+/// valued at the oracle price of the block they were received in. Outbound HNT
+/// payments are disposals, valued at the oracle price of their block and
+/// matched against the lots held at that time. The whole history of the wallet
+/// is matched, only disposals in the timespan are reported. Disposals that can
+/// not be matched against a lot have no acquisition date and a cost basis of
+/// zero. Proceeds or cost basis before the first oracle price are unknown,
+/// which leaves the gain unknown and the disposal out of the summary totals.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+pub struct Cmd {
+    /// The wallet address to report gains for
+    account: String,
+
+    #[structopt(flatten)]
+    range: RangeArgs,
+
+    /// The order to match disposals against lots in (fifo, lifo or hifo)
+    #[structopt(long, default_value)]
+    method: Method,
+
+    /// Report the totals per year and term instead of every disposal
+    #[structopt(long)]
+    summary: bool,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
+
+/// How disposals are matched against the lots held at the time
+#[derive(Debug, Default, Clone, Copy)]
+pub enum Method {
+    /// The earliest acquired lot first
+    #[default]
+    Fifo,
+    /// The latest acquired lot first
+    Lifo,
+    /// The lot with the highest cost per token first, unpriced lots last
+    Hifo,
+}
+
+impl Method {
+    /// The index of the lot to match next
+    fn pick(&self, lots: &VecDeque<Lot>) -> usize {
+        match self {
+            Self::Fifo => 0,
+            Self::Lifo => lots.len() - 1,
+            Self::Hifo => lots.iter().enumerate().fold(0, |max, (idx, lot)| {
+                if lot.price > lots[max].price {
+                    idx
+                } else {
+                    max
+                }
+            }),
+        }
+    }
+}
+
+impl std::str::FromStr for Method {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fifo" => Ok(Self::Fifo),
+            "lifo" => Ok(Self::Lifo),
+            "hifo" => Ok(Self::Hifo),
+            _ => Err(anyhow!("invalid method {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Fifo => f.write_str("fifo"),
+            Self::Lifo => f.write_str("lifo"),
+            Self::Hifo => f.write_str("hifo"),
+        }
+    }
+}
+
+/// Tokens received in a block, or sent in a transaction
+#[derive(Debug, sqlx::FromRow)]
+struct Event {
+    block: i64,
+    timestamp: DateTime<Utc>,
+    transaction_hash: String,
+    /// The amount in bones
+    amount: i64,
+    /// The oracle price in USD per token
+    price: Option<f64>,
+}
+
+/// An acquired lot and the amount of it that is still held
+#[derive(Debug)]
+struct Lot {
+    block: i64,
+    date: NaiveDate,
+    amount: i64,
+    price: Option<f64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Gain {
+    year: i32,
+    /// short or long, or unknown for a disposal without a lot
+    term: &'static str,
+    description: String,
+    date_acquired: Option<NaiveDate>,
+    date_sold: NaiveDate,
+    /// None without an oracle price at or before the block sold
+    proceeds: Option<f64>,
+    /// None without an oracle price at or before the block acquired
+    cost_basis: Option<f64>,
+    gain: Option<f64>,
+    hnt: f64,
+    block_acquired: Option<i64>,
+    block_sold: i64,
+    transaction_hash: String,
+}
+
+impl ArrowSchema for Gain {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("year", DataType::Int32, false),
+            Field::new("term", DataType::Utf8, false),
+            Field::new("description", DataType::Utf8, false),
+            Field::new("date_acquired", DataType::Date32, true),
+            Field::new("date_sold", DataType::Date32, false),
+            Field::new("proceeds", DataType::Float64, true),
+            Field::new("cost_basis", DataType::Float64, true),
+            Field::new("gain", DataType::Float64, true),
+            Field::new("hnt", DataType::Float64, false),
+            Field::new("block_acquired", DataType::Int64, true),
+            Field::new("block_sold", DataType::Int64, false),
+            Field::new("transaction_hash", DataType::Utf8, false),
+        ])
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct GainSummary {
+    year: i32,
+    term: &'static str,
+    count: i64,
+    hnt: f64,
+    proceeds: f64,
+    cost_basis: f64,
+    gain: f64,
+    /// The number of disposals with an unknown gain, left out of the totals
+    unpriced: i64,
+}
+
+impl ArrowSchema for GainSummary {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("year", DataType::Int32, false),
+            Field::new("term", DataType::Utf8, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("hnt", DataType::Float64, false),
+            Field::new("proceeds", DataType::Float64, false),
+            Field::new("cost_basis", DataType::Float64, false),
+            Field::new("gain", DataType::Float64, false),
+            Field::new("unpriced", DataType::Int64, false),
+        ])
+    }
+}
+
+const LOTS_QUERY: &str = r#"
+    select
+        r.block,
+        b.timestamp,
+        min(r.transaction_hash) as transaction_hash,
+        sum(r.amount)::int8 as amount,
+        o.price::float8 / 100000000 as price
+    from rewards r
+    inner join blocks b on b.height = r.block
+    left join oracle_prices o on o.block = (select max(o2.block) from oracle_prices o2 where o2.block <= r.block)
+    where r.block <= $1
+    and r.account = $2
+    group by r.block, b.timestamp, o.price
+    order by r.block;
+"#;
+
+const DISPOSALS_QUERY: &str = r#"
+    with txns as (
+        select distinct t.block, t.hash, t.type, t.fields
+        from transaction_actors a
+        inner join transactions t on t.hash = a.transaction_hash
+        where a.block <= $1
+            and a.actor = $2
+            and a.actor_role = 'payer'
+            and t.type in ('payment_v1', 'payment_v2')
+    ),
+    payments as (
+        select t.block, t.hash, (t.fields->>'amount')::bigint as amount
+        from txns t
+        where t.type = 'payment_v1' and t.fields->>'payer' = $2
+        union all
+        select t.block, t.hash, (p->>'amount')::bigint
+        from txns t, jsonb_array_elements(t.fields->'payments') p
+        where t.type = 'payment_v2' and t.fields->>'payer' = $2
+            and coalesce(p->>'token_type', 'hnt') = 'hnt'
+    )
+    select
+        p.block,
+        b.timestamp,
+        p.hash as transaction_hash,
+        sum(p.amount)::int8 as amount,
+        o.price::float8 / 100000000 as price
+    from payments p
+    inner join blocks b on b.height = p.block
+    left join oracle_prices o on o.block = (select max(o2.block) from oracle_prices o2 where o2.block <= p.block)
+    group by p.block, b.timestamp, p.hash, o.price
+    order by p.block, p.hash;
+"#;
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.range.resolve(pool, &opts).await?;
+        let blockspan = span.blockspan(pool, &span.timespan).await?;
+        let lots: Vec<Event> = sqlx::query_as(LOTS_QUERY)
+            .bind(blockspan.high)
+            .bind(&self.account)
+            .fetch_all(pool)
+            .await?;
+        let disposals: Vec<Event> = sqlx::query_as(DISPOSALS_QUERY)
+            .bind(blockspan.high)
+            .bind(&self.account)
+            .fetch_all(pool)
+            .await?;
+        let gains = match_lots(self.method, &opts.tz(), lots, disposals, blockspan.low);
+
+        let output = opts.output("tax-gains", &format)?;
+        if self.summary {
+            let rows = summarize(gains).into_iter().map(Ok::<_, Error>);
+            format.output(output, stream::iter(rows).boxed()).await
+        } else {
+            let rows = gains.into_iter().map(Ok::<_, Error>);
+            format.output(output, stream::iter(rows).boxed()).await
+        }
+    }
+}
+
+/// Matches the given disposals against the given lots in block order, with
+/// lots received in a block available to disposals in the same block. Returns
+/// the gains of the disposals at or after the given block.
+fn match_lots(
+    method: Method,
+    tz: &Tz,
+    lots: Vec<Event>,
+    disposals: Vec<Event>,
+    low: i64,
+) -> Vec<Gain> {
+    let date = |timestamp: DateTime<Utc>| timestamp.with_timezone(tz).date_naive();
+    let mut lots = lots.into_iter().peekable();
+    let mut held: VecDeque<Lot> = VecDeque::new();
+    let mut gains = Vec::new();
+    for disposal in disposals {
+        while let Some(lot) = lots.next_if(|lot| lot.block <= disposal.block) {
+            held.push_back(Lot {
+                block: lot.block,
+                date: date(lot.timestamp),
+                amount: lot.amount,
+                price: lot.price,
+            });
+        }
+        let date_sold = date(disposal.timestamp);
+        let mut remaining = disposal.amount;
+        while remaining > 0 {
+            let (amount, acquired) = match held.is_empty() {
+                true => (remaining, None),
+                false => {
+                    let idx = method.pick(&held);
+                    let lot = &mut held[idx];
+                    let amount = remaining.min(lot.amount);
+                    let acquired = (lot.block, lot.date, lot.price);
+                    lot.amount -= amount;
+                    if lot.amount == 0 {
+                        held.remove(idx);
+                    }
+                    (amount, Some(acquired))
+                }
+            };
+            remaining -= amount;
+            if disposal.block < low {
+                continue;
+            }
+            let hnt = amount as f64 / 100000000.0;
+            let proceeds = disposal.price.map(|price| hnt * price);
+            let cost_basis = match acquired {
+                None => Some(0.0),
+                Some((_, _, price)) => price.map(|price| hnt * price),
+            };
+            gains.push(Gain {
+                year: date_sold.year(),
+                term: term(acquired.map(|(_, date, _)| date), date_sold),
+                description: format!("{hnt:.8} HNT"),
+                date_acquired: acquired.map(|(_, date, _)| date),
+                date_sold,
+                proceeds,
+                cost_basis,
+                gain: proceeds
+                    .zip(cost_basis)
+                    .map(|(proceeds, cost)| proceeds - cost),
+                hnt,
+                block_acquired: acquired.map(|(block, _, _)| block),
+                block_sold: disposal.block,
+                transaction_hash: disposal.transaction_hash.clone(),
+            });
+        }
+    }
+    gains
+}
+
+/// Holdings sold more than a year after their acquisition are long term
+fn term(acquired: Option<NaiveDate>, sold: NaiveDate) -> &'static str {
+    match acquired.and_then(|date| date.checked_add_months(Months::new(12))) {
+        None => "unknown",
+        Some(anniversary) if sold > anniversary => "long",
+        Some(_) => "short",
+    }
+}
+
+/// The totals of the given gains per year and term
+fn summarize(gains: Vec<Gain>) -> Vec<GainSummary> {
+    let mut totals: BTreeMap<(i32, &'static str), GainSummary> = BTreeMap::new();
+    for gain in gains {
+        let total = totals
+            .entry((gain.year, gain.term))
+            .or_insert_with(|| GainSummary {
+                year: gain.year,
+                term: gain.term,
+                ..Default::default()
+            });
+        total.count += 1;
+        total.hnt += gain.hnt;
+        match (gain.proceeds, gain.cost_basis, gain.gain) {
+            (Some(proceeds), Some(cost_basis), Some(gain)) => {
+                total.proceeds += proceeds;
+                total.cost_basis += cost_basis;
+                total.gain += gain;
+            }
+            _ => total.unpriced += 1,
+        }
+    }
+    totals.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An event on the given date for a whole number of tokens
+    fn event(block: i64, date: &str, hnt: i64, price: f64) -> Event {
+        let date: NaiveDate = date.parse().unwrap();
+        Event {
+            block,
+            timestamp: date.and_hms_opt(12, 0, 0).unwrap().and_utc(),
+            transaction_hash: format!("tx{block}"),
+            amount: hnt * 100000000,
+            price: Some(price),
+        }
+    }
+
+    fn lots() -> Vec<Event> {
+        vec![
+            event(1, "2023-01-01", 10, 1.0),
+            event(2, "2023-01-02", 10, 3.0),
+            event(3, "2023-01-03", 10, 2.0),
+        ]
+    }
+
+    fn sold(gains: &[Gain]) -> Vec<(Option<i64>, f64, Option<f64>)> {
+        gains
+            .iter()
+            .map(|gain| (gain.block_acquired, gain.hnt, gain.cost_basis))
+            .collect()
+    }
+
+    #[test]
+    fn matches_by_method() {
+        let disposals = || vec![event(4, "2023-01-04", 5, 4.0)];
+        let gains = match_lots(Method::Fifo, &Tz::UTC, lots(), disposals(), 0);
+        assert_eq!(sold(&gains), vec![(Some(1), 5.0, Some(5.0))]);
+        let gains = match_lots(Method::Lifo, &Tz::UTC, lots(), disposals(), 0);
+        assert_eq!(sold(&gains), vec![(Some(3), 5.0, Some(10.0))]);
+        let gains = match_lots(Method::Hifo, &Tz::UTC, lots(), disposals(), 0);
+        assert_eq!(sold(&gains), vec![(Some(2), 5.0, Some(15.0))]);
+        assert_eq!(gains[0].proceeds, Some(20.0));
+        assert_eq!(gains[0].gain, Some(5.0));
+    }
+
+    #[test]
+    fn consumes_lots_partially() {
+        let disposals = vec![
+            event(4, "2023-01-04", 15, 4.0),
+            event(5, "2023-01-05", 10, 4.0),
+        ];
+        let gains = match_lots(Method::Fifo, &Tz::UTC, lots(), disposals, 0);
+        assert_eq!(
+            sold(&gains),
+            vec![
+                (Some(1), 10.0, Some(10.0)),
+                (Some(2), 5.0, Some(15.0)),
+                (Some(2), 5.0, Some(15.0)),
+                (Some(3), 5.0, Some(10.0)),
+            ]
+        );
+        assert!(gains[..2].iter().all(|gain| gain.block_sold == 4));
+        assert!(gains[2..].iter().all(|gain| gain.block_sold == 5));
+    }
+
+    #[test]
+    fn matches_lot_in_same_block() {
+        let disposals = vec![event(3, "2023-01-03", 5, 2.0)];
+        let gains = match_lots(Method::Lifo, &Tz::UTC, lots(), disposals, 0);
+        assert_eq!(sold(&gains), vec![(Some(3), 5.0, Some(10.0))]);
+    }
+
+    #[test]
+    fn reports_unmatched_disposals() {
+        let disposals = vec![event(4, "2023-01-04", 35, 4.0)];
+        let gains = match_lots(Method::Fifo, &Tz::UTC, lots(), disposals, 0);
+        assert_eq!(gains.len(), 4);
+        let unmatched = &gains[3];
+        assert_eq!(unmatched.hnt, 5.0);
+        assert_eq!(unmatched.block_acquired, None);
+        assert_eq!(unmatched.date_acquired, None);
+        assert_eq!(unmatched.cost_basis, Some(0.0));
+        assert_eq!(unmatched.gain, Some(20.0));
+        assert_eq!(unmatched.term, "unknown");
+    }
+
+    #[test]
+    fn skips_disposals_before_low() {
+        let disposals = vec![
+            event(2, "2023-01-02", 10, 3.0),
+            event(4, "2023-01-04", 15, 4.0),
+        ];
+        let gains = match_lots(Method::Fifo, &Tz::UTC, lots(), disposals, 3);
+        assert_eq!(
+            sold(&gains),
+            vec![(Some(2), 10.0, Some(30.0)), (Some(3), 5.0, Some(10.0))]
+        );
+        assert!(gains.iter().all(|gain| gain.block_sold == 4));
+    }
+
+    #[test]
+    fn leaves_unpriced_gains_unknown() {
+        let mut lots = lots();
+        lots[0].price = None;
+        let mut disposals = vec![event(4, "2023-01-04", 15, 4.0)];
+        disposals[0].price = None;
+        let gains = match_lots(Method::Fifo, &Tz::UTC, lots, disposals, 0);
+        assert_eq!(
+            sold(&gains),
+            vec![(Some(1), 10.0, None), (Some(2), 5.0, Some(15.0))]
+        );
+        assert!(gains.iter().all(|gain| gain.proceeds.is_none()));
+        assert!(gains.iter().all(|gain| gain.gain.is_none()));
+        assert_eq!(summarize(gains)[0].unpriced, 2);
+    }
+
+    #[test]
+    fn splits_terms_after_a_year() {
+        let acquired = NaiveDate::from_ymd_opt(2022, 1, 15);
+        let sold = |date: &str| date.parse().unwrap();
+        assert_eq!(term(acquired, sold("2022-06-01")), "short");
+        assert_eq!(term(acquired, sold("2023-01-15")), "short");
+        assert_eq!(term(acquired, sold("2023-01-16")), "long");
+        assert_eq!(term(None, sold("2023-01-16")), "unknown");
+    }
+}
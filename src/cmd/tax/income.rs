@@ -0,0 +1,81 @@
+use crate::{
+    cmd::{parquet::ArrowSchema, Format, Opts, RangeArgs},
+    Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use sqlx::postgres::PgPool;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Generates output in any --format with the reward income of a given wallet
+/// per year (in the --tz timezone). Rewards are income at receipt, valued at
+/// the oracle price of the block they were received in. Rewards received before
+/// the first oracle price have no USD value and are counted as unpriced.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+pub struct Cmd {
+    /// The wallet address to report income for
+    account: String,
+
+    #[structopt(flatten)]
+    range: RangeArgs,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct Income {
+    year: i32,
+    count: i64,
+    hnt: f64,
+    /// The USD value of the priced rewards, none when no reward is priced
+    usd_amount: Option<f64>,
+    /// The number of rewards without an oracle price at or before their block
+    unpriced: i64,
+}
+
+impl ArrowSchema for Income {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("year", DataType::Int32, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("hnt", DataType::Float64, false),
+            Field::new("usd_amount", DataType::Float64, true),
+            Field::new("unpriced", DataType::Int64, false),
+        ])
+    }
+}
+
+const INCOME_QUERY: &str = r#"
+    select
+        date_part('year', b.timestamp at time zone $4)::int4 as year,
+        count(*) as count,
+        sum(r.amount)::float8 / 100000000 as hnt,
+        sum((r.amount::float8 / 100000000) * (o.price::float8 / 100000000)) as usd_amount,
+        count(*) filter (where o.price is null) as unpriced
+    from rewards r
+    inner join blocks b on b.height = r.block
+    left join oracle_prices o on o.block = (select max(o2.block) from oracle_prices o2 where o2.block <= r.block)
+    where r.block between $1 and $2
+    and r.account = $3
+    group by 1
+    order by 1;
+"#;
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.range.resolve(pool, &opts).await?;
+        let tz = opts.tz();
+        opts.output_timespan(pool, "tax-income", &format, &span, |_, blockspan| {
+            sqlx::query_as::<_, Income>(INCOME_QUERY)
+                .bind(blockspan.low)
+                .bind(blockspan.high)
+                .bind(&self.account)
+                .bind(tz.name())
+                .fetch(pool)
+        })
+        .await
+    }
+}
@@ -0,0 +1,21 @@
+use crate::{cmd::Opts, Result};
+use sqlx::PgPool;
+use structopt::StructOpt;
+
+mod gains;
+mod income;
+
+#[derive(Debug, StructOpt)]
+pub enum Cmd {
+    Income(income::Cmd),
+    Gains(gains::Cmd),
+}
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        match self {
+            Self::Income(cmd) => cmd.run(pool, opts).await,
+            Self::Gains(cmd) => cmd.run(pool, opts).await,
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    cmd::{parquet::ArrowSchema, DateArgs, Format, Opts, Output},
+    Error, Result,
+};
+use arrow_schema::{DataType, Field, Schema};
+use futures::stream::{self, StreamExt};
+use sqlx::postgres::PgPool;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+/// Follows payments breadth-first from a given wallet for a number of hops and
+/// generates CSV or JSON output with the resulting edge list. The graph can
+/// optionally be written to a GraphML or DOT file as well. Wallets that were
+/// already visited are not followed again, so cycles terminate the walk. Only
+/// payments made at or after the block the first payment to a wallet arrived
+/// in are followed from it.
+#[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
+pub struct Cmd {
+    /// The wallet address to start tracing from
+    account: String,
+
+    #[structopt(flatten)]
+    date: DateArgs,
+
+    /// The maximum number of hops to follow from the starting wallet
+    #[structopt(long, default_value = "3")]
+    depth: i64,
+
+    /// The minimum amount of a single payment to follow
+    #[structopt(long, default_value = "0")]
+    min_amount: f64,
+
+    /// The token to follow payments for
+    #[structopt(long, default_value = "hnt")]
+    token: String,
+
+    /// File to write the traced graph to
+    #[structopt(long)]
+    graph: Option<PathBuf>,
+
+    /// The format to write the traced graph in (graphml or dot)
+    #[structopt(long, default_value)]
+    graph_format: GraphFormat,
+
+    /// The output format (json, ndjson, csv or parquet)
+    #[structopt(long)]
+    format: Option<Format>,
+}
+
+#[derive(Debug, serde::Serialize, sqlx::FromRow)]
+pub struct Edge {
+    #[sqlx(default)]
+    hop: i64,
+    source: String,
+    target: String,
+    count: i64,
+    amount: f64,
+    first_block: i64,
+    last_block: i64,
+}
+
+impl ArrowSchema for Edge {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("hop", DataType::Int64, false),
+            Field::new("source", DataType::Utf8, false),
+            Field::new("target", DataType::Utf8, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("amount", DataType::Float64, false),
+            Field::new("first_block", DataType::Int64, false),
+            Field::new("last_block", DataType::Int64, false),
+        ])
+    }
+}
+
+/// The payments made by the given wallets, each at or after its own first
+/// block
+const PAYMENTS_QUERY: &str = r#"
+    with frontier as (
+        select * from unnest($3::text[], $6::int8[]) as f(address, first_block)
+    ),
+    txns as (
+        select distinct t.block, t.hash, t.type, t.fields
+        from transaction_actors a
+        inner join frontier f on f.address = a.actor
+        inner join transactions t on t.hash = a.transaction_hash
+        where a.block between $1 and $2
+            and a.block >= f.first_block
+            and a.actor_role = 'payer'
+            and t.type in ('payment_v1', 'payment_v2')
+    ),
+    payments as (
+        select
+            t.block,
+            t.fields->>'payer' as payer,
+            t.fields->>'payee' as payee,
+            'hnt' as token,
+            (t.fields->>'amount')::bigint as amount
+        from txns t
+        where t.type = 'payment_v1'
+        union all
+        select
+            t.block,
+            t.fields->>'payer',
+            p->>'payee',
+            coalesce(p->>'token_type', 'hnt'),
+            (p->>'amount')::bigint
+        from txns t, jsonb_array_elements(t.fields->'payments') p
+        where t.type = 'payment_v2'
+    )
+    select
+        payer as source,
+        payee as target,
+        count(*) as count,
+        sum(amount)::float8 / 100000000 as amount,
+        min(block) as first_block,
+        max(block) as last_block
+    from payments p
+    inner join frontier f on f.address = p.payer
+    where p.block >= f.first_block
+        and token = $4
+        and amount >= $5
+    group by payer, payee
+    order by payer, amount desc;
+"#;
+
+impl Cmd {
+    pub async fn run(&self, pool: &PgPool, opts: Opts) -> Result {
+        let format = opts.format(self.format);
+        let span = self.date.resolve(pool, &opts, 1).await?;
+        let blockspan = span.blockspan(pool, &span.timespan).await?;
+        let min_amount = (self.min_amount * 100_000_000.0) as i64;
+
+        let mut visited = HashSet::from([self.account.clone()]);
+        let mut nodes = vec![(self.account.clone(), 0)];
+        let mut edges = vec![];
+        // The wallets to follow next, with the block funds first reached them in
+        let mut frontier = HashMap::from([(self.account.clone(), blockspan.low)]);
+        for hop in 1..=self.depth {
+            if frontier.is_empty() {
+                break;
+            }
+            let (addresses, first_blocks): (Vec<String>, Vec<i64>) = frontier.drain().unzip();
+            let hop_edges = sqlx::query_as::<_, Edge>(PAYMENTS_QUERY)
+                .bind(blockspan.low)
+                .bind(blockspan.high)
+                .bind(&addresses)
+                .bind(&self.token)
+                .bind(min_amount)
+                .bind(&first_blocks)
+                .fetch_all(pool)
+                .await?;
+            for mut edge in hop_edges {
+                edge.hop = hop;
+                if visited.insert(edge.target.clone()) {
+                    nodes.push((edge.target.clone(), hop));
+                    frontier.insert(edge.target.clone(), edge.first_block);
+                } else if let Some(first_block) = frontier.get_mut(&edge.target) {
+                    *first_block = edge.first_block.min(*first_block);
+                }
+                edges.push(edge);
+            }
+        }
+
+        if let Some(path) = &self.graph {
+            let mut output = Output::create(path)?;
+            self.graph_format.write(&mut output, &nodes, &edges)?;
+            output.finish()?;
+        }
+
+        let rows = stream::iter(edges.into_iter().map(Ok::<_, Error>)).boxed();
+        format.output(opts.output("trace", &format)?, rows).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub enum GraphFormat {
+    #[default]
+    GraphMl,
+    Dot,
+}
+
+impl GraphFormat {
+    fn write<W: Write>(&self, mut output: W, nodes: &[(String, i64)], edges: &[Edge]) -> Result {
+        match self {
+            Self::GraphMl => {
+                writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+                writeln!(
+                    output,
+                    r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+                )?;
+                for (key, target, kind) in [
+                    ("hop", "node", "long"),
+                    ("amount", "edge", "double"),
+                    ("count", "edge", "long"),
+                    ("first_block", "edge", "long"),
+                    ("last_block", "edge", "long"),
+                ] {
+                    writeln!(
+                        output,
+                        r#"  <key id="{key}" for="{target}" attr.name="{key}" attr.type="{kind}"/>"#
+                    )?;
+                }
+                writeln!(output, r#"  <graph id="trace" edgedefault="directed">"#)?;
+                for (address, hop) in nodes {
+                    writeln!(
+                        output,
+                        r#"    <node id="{}"><data key="hop">{hop}</data></node>"#,
+                        xml_escape(address)
+                    )?;
+                }
+                for edge in edges {
+                    writeln!(
+                        output,
+                        r#"    <edge source="{}" target="{}"><data key="amount">{}</data><data key="count">{}</data><data key="first_block">{}</data><data key="last_block">{}</data></edge>"#,
+                        xml_escape(&edge.source),
+                        xml_escape(&edge.target),
+                        edge.amount,
+                        edge.count,
+                        edge.first_block,
+                        edge.last_block,
+                    )?;
+                }
+                writeln!(output, "  </graph>")?;
+                writeln!(output, "</graphml>")?;
+            }
+            Self::Dot => {
+                writeln!(output, "digraph trace {{")?;
+                for (address, hop) in nodes {
+                    writeln!(output, "  {:?} [hop={hop}];", address)?;
+                }
+                for edge in edges {
+                    writeln!(
+                        output,
+                        "  {:?} -> {:?} [label=\"{}\", amount={}, count={}, first_block={}, last_block={}];",
+                        edge.source,
+                        edge.target,
+                        edge.amount,
+                        edge.amount,
+                        edge.count,
+                        edge.first_block,
+                        edge.last_block,
+                    )?;
+                }
+                writeln!(output, "}}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl std::str::FromStr for GraphFormat {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        use anyhow::anyhow;
+        match s.to_ascii_lowercase().as_str() {
+            "graphml" => Ok(Self::GraphMl),
+            "dot" => Ok(Self::Dot),
+            _ => Err(anyhow!("invalid graph format {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::GraphMl => f.write_str("graphml"),
+            Self::Dot => f.write_str("dot"),
+        }
+    }
+}
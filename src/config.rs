@@ -0,0 +1,97 @@
+//! Configuration file support.
+//!
+//! An `etl-extract.toml` file holds named profiles:
+//!
+//! ```toml
+//! [profiles.default]
+//! database_url = "postgres://etl@localhost/etl"
+//! read_database_url = "postgres://etl@replica/etl"
+//! statement_timeout = "10m"
+//! format = "csv"
+//! output = "/data/extracts"
+//! concurrency = 20
+//! require_fresh = "15m"
+//! timezone = "America/New_York"
+//! ```
+//!
+//! Settings are resolved in the following order, the first one that is set
+//! wins:
+//!
+//! 1. command line flags
+//! 2. environment variables (`DATABASE_URL`, `READ_DATABASE_URL`), including
+//!    those set in the `-e` and `.env` files
+//! 3. the selected profile (`--profile`, or `default` when present)
+//! 4. built-in defaults
+//!
+//! The read database url is the exception: it is only taken from the level
+//! the database url is set at or a higher one, so `--database-url` ignores a
+//! `READ_DATABASE_URL` from the environment.
+use crate::Result;
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// The config file that is read when no `--config` is given and it exists in
+/// the current directory.
+pub const DEFAULT_CONFIG: &str = "etl-extract.toml";
+
+/// The profile that is used when no `--profile` is given and it exists.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub database_url: Option<String>,
+    pub read_database_url: Option<String>,
+    pub max_connections: Option<u32>,
+    pub acquire_timeout: Option<String>,
+    pub statement_timeout: Option<String>,
+    pub application_name: Option<String>,
+    pub format: Option<String>,
+    pub output: Option<PathBuf>,
+    pub concurrency: Option<usize>,
+    pub require_fresh: Option<String>,
+    pub cache_file: Option<PathBuf>,
+    pub timezone: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("parsing config {}", path.display()))
+    }
+
+    /// Loads the profile with the given name from the given config file. When
+    /// no file is given the default config file is used if it exists. When no
+    /// name is given the default profile is used if it exists.
+    pub fn load_profile(path: Option<&Path>, name: Option<&str>) -> Result<Profile> {
+        let config = match path {
+            Some(path) => Self::load(path)?,
+            None if Path::new(DEFAULT_CONFIG).exists() => Self::load(Path::new(DEFAULT_CONFIG))?,
+            None => Self::default(),
+        };
+        match name {
+            Some(name) => config
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("profile {name} not found")),
+            None => Ok(config
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default()),
+        }
+    }
+}
@@ -1,10 +1,12 @@
 pub type Result<T = ()> = anyhow::Result<T>;
 pub type Error = anyhow::Error;
 
+pub mod cache;
 pub mod cmd;
+pub mod config;
 
 mod blockspan;
 mod timespan;
 
-pub use blockspan::BlockSpan;
-pub use timespan::TimeSpan;
+pub use blockspan::{BlockSpan, BlockSpanError, ChainBounds};
+pub use timespan::{Moment, Partition, Period, TimeSpan, Unit};
@@ -1,8 +1,10 @@
 use etl_exporter::{
-    cmd::{balance, blocks, hotspots, rewards, supply, Opts},
+    cmd::{
+        accounts, balance, blocks, cache, flow, hotspots, oracle, rewards, status, supply, tax,
+        trace, Opts,
+    },
     Result,
 };
-use sqlx::postgres::PgPool;
 use std::process;
 use structopt::StructOpt;
 
@@ -22,24 +24,36 @@ pub enum Cmd {
     Hotspots(hotspots::Cmd),
     Supply(supply::Cmd),
     Balance(balance::Cmd),
+    Accounts(accounts::Cmd),
+    Flow(flow::Cmd),
+    Oracle(oracle::Cmd),
+    Trace(trace::Cmd),
+    Tax(tax::Cmd),
+    Status(status::Cmd),
+    Cache(cache::Cmd),
 }
 
 #[tokio::main]
 async fn main() -> Result {
     let cli = Cli::from_args();
     if let Err(e) = run(cli).await {
-        eprintln!("error: {:?}", e);
+        eprintln!("error: {e:#}");
         process::exit(1);
     }
 
     Ok(())
 }
 
-async fn run(cli: Cli) -> Result {
+async fn run(mut cli: Cli) -> Result {
     if let Some(ref path) = cli.opts.env {
         dotenv::from_path(path)?;
     }
-    let pool = PgPool::connect(&dotenv::var("DATABASE_URL")?).await?;
+    cli.opts.load_config()?;
+    if let Cmd::Cache(cmd) = cli.cmd {
+        return cmd.run(cli.opts);
+    }
+    let pool = cli.opts.connect().await?;
+    cli.opts.open_cache().await?;
 
     match cli.cmd {
         Cmd::Blocks(cmd) => cmd.run(&pool, cli.opts).await,
@@ -47,5 +61,12 @@ async fn run(cli: Cli) -> Result {
         Cmd::Hotspots(cmd) => cmd.run(&pool, cli.opts).await,
         Cmd::Supply(cmd) => cmd.run(&pool, cli.opts).await,
         Cmd::Balance(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Accounts(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Flow(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Oracle(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Trace(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Tax(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Status(cmd) => cmd.run(&pool, cli.opts).await,
+        Cmd::Cache(cmd) => cmd.run(cli.opts),
     }
 }
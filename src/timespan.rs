@@ -1,21 +1,33 @@
-use chrono::{DateTime, Duration, NaiveDate, Utc};
+use anyhow::{anyhow, bail};
+use chrono::{
+    DateTime, Datelike, Days, Duration, FixedOffset, LocalResult, Months, NaiveDate, NaiveDateTime,
+    TimeZone, Utc,
+};
+use chrono_tz::Tz;
 use serde::Serialize;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct TimeSpan {
     pub low: DateTime<Utc>,
     pub high: DateTime<Utc>,
 }
 
 impl TimeSpan {
-    pub fn today() -> Self {
+    pub fn today() -> crate::Result<Self> {
         Self::new(Utc::now().date_naive(), 1)
     }
 
-    pub fn new<S: ToDateTimeUtc>(start: S, days: i64) -> Self {
-        let start = start.to_datetime();
-        let end = start + Duration::days(days);
-        Self::for_date_range(start, end)
+    /// Creates a timespan from the given start and number of calendar days.
+    /// Days can be negative.
+    pub fn new<S: ToDateTimeUtc>(start: S, days: i64) -> crate::Result<Self> {
+        Self::for_period(start, &Period::days(days))
+    }
+
+    /// Creates a timespan from the given start and period. The period can be
+    /// negative, in which case the timespan ends at the given start.
+    pub fn for_period<S: ToDateTimeUtc>(start: S, period: &Period) -> crate::Result<Self> {
+        let end = start.clone().add_period(period)?;
+        Ok(Self::for_date_range(start, end))
     }
 
     pub fn for_date_range<S: ToDateTimeUtc, E: ToDateTimeUtc>(start: S, end: E) -> Self {
@@ -26,20 +38,691 @@ impl TimeSpan {
             high: std::cmp::max(start, end),
         }
     }
+
+    /// The number of days covered by the timespan, rounded to the nearest
+    /// whole day to account for daylight saving time transitions.
+    pub fn days(&self) -> i64 {
+        (self.high - self.low + Duration::hours(12)).num_days()
+    }
+
+    /// Splits the timespan into consecutive timespans that each cover at most
+    /// one calendar period in the given timezone. The first and last
+    /// timespans are clipped to the bounds of this timespan.
+    pub fn partition(&self, period: Partition, tz: &Tz) -> Vec<Self> {
+        let mut spans = vec![];
+        let mut low = self.low;
+        while low < self.high {
+            let high = std::cmp::min(period.next_start(low, tz), self.high);
+            spans.push(Self { low, high });
+            low = high;
+        }
+        spans
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Partition {
+    Day,
+    Week,
+    Month,
+}
+
+impl Partition {
+    /// Returns the start of the period following the one the given time is in
+    fn next_start(&self, time: DateTime<Utc>, tz: &Tz) -> DateTime<Utc> {
+        let date = time.with_timezone(tz).date_naive();
+        let next = match self {
+            Self::Day => date + Duration::days(1),
+            Self::Week => date.week(chrono::Weekday::Mon).last_day() + Duration::days(1),
+            Self::Month => date.with_day(1).unwrap() + Months::new(1),
+        };
+        start_of_day(tz, next).to_datetime()
+    }
+}
+
+impl std::str::FromStr for Partition {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        use anyhow::anyhow;
+        match s.to_ascii_lowercase().as_str() {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            _ => Err(anyhow!("invalid partition {s}")),
+        }
+    }
 }
 
-pub trait ToDateTimeUtc {
+impl std::fmt::Display for Partition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Day => f.write_str("day"),
+            Self::Week => f.write_str("week"),
+            Self::Month => f.write_str("month"),
+        }
+    }
+}
+
+/// Returns the first instant of the given date in the given timezone. This is
+/// midnight unless a daylight saving time transition skips it.
+pub fn start_of_day<T: TimeZone>(tz: &T, date: NaiveDate) -> DateTime<T> {
+    localize(tz, date.and_hms_opt(0, 0, 0).unwrap())
+}
+
+/// Converts a local time in the given timezone to an instant. Ambiguous local
+/// times resolve to the earliest instant, and local times that fall in a
+/// daylight saving time gap move forward to the end of the gap.
+fn localize<T: TimeZone>(tz: &T, mut time: NaiveDateTime) -> DateTime<T> {
+    loop {
+        match tz.from_local_datetime(&time) {
+            LocalResult::Single(time) => return time,
+            LocalResult::Ambiguous(earliest, _) => return earliest,
+            LocalResult::None => time += Duration::minutes(1),
+        }
+    }
+}
+
+pub trait ToDateTimeUtc: Clone {
     fn to_datetime(self) -> DateTime<Utc>;
+
+    /// Returns the instant the given period after this one. The calendar part
+    /// of the period is applied in UTC.
+    fn add_period(self, period: &Period) -> crate::Result<DateTime<Utc>> {
+        period.add_to(&Utc, self.to_datetime().naive_utc())
+    }
 }
 
 impl ToDateTimeUtc for NaiveDate {
     fn to_datetime(self) -> DateTime<Utc> {
-        DateTime::from_utc(self.and_hms_opt(0, 0, 0).unwrap(), Utc)
+        DateTime::from_naive_utc_and_offset(self.and_hms_opt(0, 0, 0).unwrap(), Utc)
     }
 }
 
-impl ToDateTimeUtc for DateTime<Utc> {
+impl<T: TimeZone> ToDateTimeUtc for DateTime<T> {
     fn to_datetime(self) -> DateTime<Utc> {
-        self
+        self.with_timezone(&Utc)
+    }
+
+    fn add_period(self, period: &Period) -> crate::Result<DateTime<Utc>> {
+        period.add_to(&self.timezone(), self.naive_local())
+    }
+}
+
+/// A point in time given on the command line. Besides dates, which start at
+/// midnight in the configured timezone, and RFC3339 timestamps, a number of
+/// expressions are understood that resolve relative to the current date in the
+/// configured timezone:
+///
+/// * `today`, `yesterday` and `tomorrow`
+/// * `last-`, `this-` or `next-` followed by `day`, `week`, `month`, `quarter`
+///   or `year`, e.g. `last-month`
+/// * a negative offset from the start of today in days, weeks, months or
+///   years, or from now in hours, e.g. `-7d` or `-6h`
+/// * a quarter or ISO week of a year, e.g. `2023-Q2` or `2023-W14`
+///
+/// Expressions other than plain dates and timestamps also cover an extent,
+/// like the whole of last month or the 7 days before today, which is used as
+/// the length of the timespan when no other length is given.
+#[derive(Debug, Clone)]
+pub enum Moment {
+    Date(NaiveDate),
+    Time(DateTime<FixedOffset>),
+    /// A calendar period that starts at the given date
+    Calendar(NaiveDate, Unit),
+    /// A calendar period relative to the current one, e.g. last-month is a
+    /// month with an offset of -1
+    Current(Unit, i32),
+    /// The given period before the start of today, or before now for periods
+    /// in hours
+    Ago(Period),
+}
+
+impl Moment {
+    /// Resolves the moment to an instant in the given timezone. Expressions
+    /// resolve to the start of their extent.
+    pub fn resolve(&self, tz: &Tz) -> crate::Result<DateTime<Tz>> {
+        let now = Utc::now().with_timezone(tz);
+        let time = match self {
+            Self::Date(date) => start_of_day(tz, *date),
+            Self::Time(time) => time.with_timezone(tz),
+            Self::Calendar(date, _) => start_of_day(tz, *date),
+            Self::Current(unit, offset) => {
+                let date = unit.shift(unit.first_day(now.date_naive()), *offset);
+                start_of_day(tz, date)
+            }
+            Self::Ago(period) if period.seconds > 0 => {
+                now.add_period(&period.negated())?.with_timezone(tz)
+            }
+            Self::Ago(period) => start_of_day(tz, now.date_naive())
+                .add_period(&period.negated())?
+                .with_timezone(tz),
+        };
+        Ok(time)
+    }
+
+    /// The length of the period covered by a date expression
+    pub fn extent(&self) -> Option<Period> {
+        match self {
+            Self::Date(_) | Self::Time(_) => None,
+            Self::Calendar(_, unit) | Self::Current(unit, _) => Some(unit.period()),
+            Self::Ago(period) => Some(period.clone()),
+        }
+    }
+}
+
+impl std::str::FromStr for Moment {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(date) = NaiveDate::from_str(s) {
+            return Ok(Self::Date(date));
+        }
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Time(time));
+        }
+        parse_expression(&s.to_ascii_lowercase()).ok_or_else(|| {
+            anyhow!(
+                "invalid date {s}, expected a date, an RFC3339 timestamp or an expression \
+                 like yesterday, -7d, last-month, 2023-Q2 or 2023-W14"
+            )
+        })
+    }
+}
+
+fn parse_expression(s: &str) -> Option<Moment> {
+    match s {
+        "today" => return Some(Moment::Current(Unit::Day, 0)),
+        "yesterday" => return Some(Moment::Current(Unit::Day, -1)),
+        "tomorrow" => return Some(Moment::Current(Unit::Day, 1)),
+        _ => (),
+    }
+    if let Some(offset) = s.strip_prefix('-') {
+        let (idx, _) = offset.char_indices().next_back()?;
+        let (number, unit) = offset.split_at(idx);
+        let number: u32 = number.parse().ok()?;
+        let period = match unit {
+            "h" => Period::seconds(i64::from(number) * 3600),
+            "d" => Period::days(i64::from(number)),
+            "w" => Period::days(i64::from(number) * 7),
+            "m" => Period::months(number),
+            "y" => Period::months(number.checked_mul(12)?),
+            _ => return None,
+        };
+        return Some(Moment::Ago(period));
+    }
+    let (prefix, suffix) = s.split_once('-')?;
+    let offset = match prefix {
+        "last" => Some(-1),
+        "this" => Some(0),
+        "next" => Some(1),
+        _ => None,
+    };
+    if let Some(offset) = offset {
+        return Some(Moment::Current(suffix.parse().ok()?, offset));
+    }
+    let year: i32 = prefix.parse().ok()?;
+    if let Some(quarter) = suffix.strip_prefix('q') {
+        let quarter: u32 = quarter.parse().ok().filter(|q| (1..=4).contains(q))?;
+        let date = NaiveDate::from_ymd_opt(year, (quarter - 1) * 3 + 1, 1)?;
+        return Some(Moment::Calendar(date, Unit::Quarter));
+    }
+    let week: u32 = suffix.strip_prefix('w')?.parse().ok()?;
+    let date = NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon)?;
+    Some(Moment::Calendar(date, Unit::Week))
+}
+
+/// A calendar unit of time. Weeks start on Monday.
+#[derive(Debug, Clone, Copy)]
+pub enum Unit {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl Unit {
+    /// The first day of the unit the given date is in
+    fn first_day(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date.week(chrono::Weekday::Mon).first_day(),
+            Self::Month => date.with_day(1).unwrap(),
+            Self::Quarter => {
+                NaiveDate::from_ymd_opt(date.year(), (date.month0() / 3) * 3 + 1, 1).unwrap()
+            }
+            Self::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap(),
+        }
+    }
+
+    /// Moves the given date by a number of units
+    fn shift(&self, date: NaiveDate, units: i32) -> NaiveDate {
+        let months = match self {
+            Self::Day => return date + Duration::days(units.into()),
+            Self::Week => return date + Duration::weeks(units.into()),
+            Self::Month => Months::new(units.unsigned_abs()),
+            Self::Quarter => Months::new(units.unsigned_abs() * 3),
+            Self::Year => Months::new(units.unsigned_abs() * 12),
+        };
+        if units < 0 {
+            date - months
+        } else {
+            date + months
+        }
+    }
+
+    fn period(&self) -> Period {
+        match self {
+            Self::Day => Period::days(1),
+            Self::Week => Period::days(7),
+            Self::Month => Period::months(1),
+            Self::Quarter => Period::months(3),
+            Self::Year => Period::months(12),
+        }
+    }
+}
+
+impl std::str::FromStr for Unit {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            "month" => Ok(Self::Month),
+            "quarter" => Ok(Self::Quarter),
+            "year" => Ok(Self::Year),
+            _ => Err(anyhow!("invalid unit {s}")),
+        }
+    }
+}
+
+/// Returns the dates from the first to the last date (inclusive) that are a
+/// whole number of steps apart. The step must be a positive number of days,
+/// weeks, months or years.
+pub fn date_series(
+    first: NaiveDate,
+    last: NaiveDate,
+    step: &Period,
+) -> crate::Result<Vec<NaiveDate>> {
+    if step.negative || step.seconds != 0 || (step.months == 0 && step.days == 0) {
+        bail!("the step must be a positive number of days, weeks, months or years");
+    }
+    let mut dates = vec![];
+    for n in 0u32.. {
+        // A date past the supported range is past the last date too
+        let Some(date) = step
+            .months
+            .checked_mul(n)
+            .and_then(|months| first.checked_add_months(Months::new(months)))
+            .zip((step.days as u64).checked_mul(u64::from(n)))
+            .and_then(|(date, days)| date.checked_add_days(Days::new(days)))
+        else {
+            break;
+        };
+        if date > last {
+            break;
+        }
+        dates.push(date);
+    }
+    Ok(dates)
+}
+
+/// An ISO-8601 duration like `P1W` or `PT6H`, optionally preceded by a minus
+/// sign. A plain number is a number of days. Years, months, weeks and days
+/// are calendar periods in the timezone they are applied in, hours, minutes
+/// and seconds are exact.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Period {
+    negative: bool,
+    months: u32,
+    days: i64,
+    seconds: i64,
+}
+
+impl Period {
+    pub fn days(days: i64) -> Self {
+        Self {
+            negative: days < 0,
+            days: days.saturating_abs(),
+            ..Default::default()
+        }
+    }
+
+    fn months(months: u32) -> Self {
+        Self {
+            months,
+            ..Default::default()
+        }
+    }
+
+    fn seconds(seconds: i64) -> Self {
+        Self {
+            negative: seconds < 0,
+            seconds: seconds.saturating_abs(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    fn negated(&self) -> Self {
+        Self {
+            negative: !self.negative,
+            ..self.clone()
+        }
+    }
+
+    /// Adds the period to the given local time in the given timezone. Fails
+    /// when the result is outside of the supported range of dates.
+    fn add_to<T: TimeZone>(&self, tz: &T, time: NaiveDateTime) -> crate::Result<DateTime<Utc>> {
+        let sign = if self.negative { -1 } else { 1 };
+        let months = Months::new(self.months);
+        let out_of_range = || anyhow!("date out of range, use a shorter period");
+        let time = if self.negative {
+            time.checked_sub_months(months)
+        } else {
+            time.checked_add_months(months)
+        }
+        .ok_or_else(out_of_range)?;
+        let time = Duration::try_days(sign * self.days)
+            .and_then(|days| time.checked_add_signed(days))
+            .ok_or_else(out_of_range)?;
+        Duration::try_seconds(sign * self.seconds)
+            .and_then(|seconds| localize(tz, time).to_datetime().checked_add_signed(seconds))
+            .ok_or_else(out_of_range)
+    }
+}
+
+impl std::str::FromStr for Period {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(days) = s.parse::<i64>() {
+            return Ok(Self::days(days));
+        }
+        let (negative, rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let Some(rest) = rest.strip_prefix(['P', 'p']) else {
+            bail!("invalid period {s}, expected a number of days or an ISO-8601 duration");
+        };
+        let mut period = Self {
+            negative,
+            ..Default::default()
+        };
+        let mut in_time = false;
+        let mut number = String::new();
+        let mut empty = true;
+        let mut months: i64 = 0;
+        for c in rest.chars() {
+            match c.to_ascii_uppercase() {
+                'T' if !in_time && number.is_empty() => {
+                    in_time = true;
+                    continue;
+                }
+                c if c.is_ascii_digit() => {
+                    number.push(c);
+                    continue;
+                }
+                _ if number.is_empty() => bail!("invalid period {s}"),
+                _ => (),
+            }
+            let value: i64 = number.parse()?;
+            number.clear();
+            empty = false;
+            let (total, factor) = match (in_time, c.to_ascii_uppercase()) {
+                (false, 'Y') => (&mut months, 12),
+                (false, 'M') => (&mut months, 1),
+                (false, 'W') => (&mut period.days, 7),
+                (false, 'D') => (&mut period.days, 1),
+                (true, 'H') => (&mut period.seconds, 3600),
+                (true, 'M') => (&mut period.seconds, 60),
+                (true, 'S') => (&mut period.seconds, 1),
+                _ => bail!("invalid period {s}"),
+            };
+            *total = value
+                .checked_mul(factor)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(|| anyhow!("invalid period {s}, out of range"))?;
+        }
+        if empty || !number.is_empty() {
+            bail!("invalid period {s}");
+        }
+        period.months =
+            u32::try_from(months).map_err(|_| anyhow!("invalid period {s}, out of range"))?;
+        Ok(period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::{New_York, Sao_Paulo};
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn today(tz: &Tz) -> NaiveDate {
+        Utc::now().with_timezone(tz).date_naive()
+    }
+
+    #[test]
+    fn parses_relative_expressions() {
+        assert!(matches!(
+            parse_expression("today"),
+            Some(Moment::Current(Unit::Day, 0))
+        ));
+        assert!(matches!(
+            parse_expression("last-month"),
+            Some(Moment::Current(Unit::Month, -1))
+        ));
+        assert!(matches!(
+            parse_expression("next-quarter"),
+            Some(Moment::Current(Unit::Quarter, 1))
+        ));
+        let Some(Moment::Ago(period)) = parse_expression("-7d") else {
+            panic!("expected an offset");
+        };
+        assert_eq!(period, Period::days(7));
+        let Some(Moment::Ago(period)) = parse_expression("-6h") else {
+            panic!("expected an offset");
+        };
+        assert_eq!(period, Period::seconds(6 * 3600));
+        let Some(Moment::Ago(period)) = parse_expression("-2y") else {
+            panic!("expected an offset");
+        };
+        assert_eq!(period, Period::months(24));
+    }
+
+    #[test]
+    fn parses_quarters_and_weeks() {
+        let Some(Moment::Calendar(start, Unit::Quarter)) = parse_expression("2023-q2") else {
+            panic!("expected a quarter");
+        };
+        assert_eq!(start, date("2023-04-01"));
+        let Some(Moment::Calendar(start, Unit::Week)) = parse_expression("2023-w14") else {
+            panic!("expected a week");
+        };
+        assert_eq!(start, date("2023-04-03"));
+        assert!(matches!(
+            "2023-Q2".parse::<Moment>(),
+            Ok(Moment::Calendar(_, Unit::Quarter))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for s in [
+            "",
+            "-",
+            "-d",
+            "-7",
+            "-7x",
+            "-7é",
+            "-é",
+            "-1.5d",
+            "soon",
+            "last-decade",
+            "2023-q0",
+            "2023-q5",
+            "2023-w54",
+            "2023-x1",
+            "q2-2023",
+        ] {
+            assert!(parse_expression(s).is_none(), "{s}");
+        }
+        assert!("-99999999999y".parse::<Moment>().is_err());
+    }
+
+    #[test]
+    fn resolves_moments() {
+        let tz = New_York;
+        let resolve = |s: &str| s.parse::<Moment>().unwrap().resolve(&tz).unwrap();
+        assert_eq!(resolve("2023-03-12"), utc("2023-03-12T05:00:00Z"));
+        assert_eq!(
+            resolve("2023-03-12T12:00:00+02:00"),
+            utc("2023-03-12T10:00:00Z")
+        );
+        assert_eq!(resolve("2023-Q2"), utc("2023-04-01T04:00:00Z"));
+        assert_eq!(resolve("today"), start_of_day(&tz, today(&tz)));
+        assert_eq!(
+            resolve("yesterday"),
+            start_of_day(&tz, today(&tz) - Duration::days(1))
+        );
+        assert_eq!(
+            resolve("-7d"),
+            start_of_day(&tz, today(&tz) - Duration::days(7))
+        );
+        assert_eq!(
+            resolve("this-month"),
+            start_of_day(&tz, today(&tz).with_day(1).unwrap())
+        );
+        let ago = Utc::now() - resolve("-6h").with_timezone(&Utc);
+        assert!((ago - Duration::hours(6)).num_seconds().abs() < 60);
+    }
+
+    #[test]
+    fn starts_day_after_a_gap_at_midnight() {
+        // Daylight saving time started at midnight in Sao Paulo in 2018
+        assert_eq!(
+            start_of_day(&Sao_Paulo, date("2018-11-04")),
+            utc("2018-11-04T03:00:00Z")
+        );
+        assert_eq!(
+            start_of_day(&Sao_Paulo, date("2018-11-05")),
+            utc("2018-11-05T02:00:00Z")
+        );
+    }
+
+    #[test]
+    fn localizes_gaps_and_overlaps() {
+        let local = |s: &str| s.parse::<NaiveDateTime>().unwrap();
+        // 02:30 does not exist on the day clocks move forward
+        assert_eq!(
+            localize(&New_York, local("2023-03-12T02:30:00")),
+            utc("2023-03-12T07:00:00Z")
+        );
+        // 01:30 happens twice on the day clocks move back
+        assert_eq!(
+            localize(&New_York, local("2023-11-05T01:30:00")),
+            utc("2023-11-05T05:30:00Z")
+        );
+    }
+
+    #[test]
+    fn spans_days_across_transitions() {
+        let spring = TimeSpan::new(start_of_day(&New_York, date("2023-03-12")), 1).unwrap();
+        assert_eq!(spring.high - spring.low, Duration::hours(23));
+        assert_eq!(spring.days(), 1);
+        let fall = TimeSpan::new(start_of_day(&New_York, date("2023-11-05")), 1).unwrap();
+        assert_eq!(fall.high - fall.low, Duration::hours(25));
+        assert_eq!(fall.days(), 1);
+    }
+
+    #[test]
+    fn finds_next_partition_start() {
+        let tz = New_York;
+        let next = |partition: Partition, s: &str| partition.next_start(utc(s), &tz);
+        // Wednesday, Sunday and Monday
+        assert_eq!(
+            next(Partition::Week, "2023-01-04T12:00:00Z"),
+            utc("2023-01-09T05:00:00Z")
+        );
+        assert_eq!(
+            next(Partition::Week, "2023-01-08T23:00:00Z"),
+            utc("2023-01-09T05:00:00Z")
+        );
+        assert_eq!(
+            next(Partition::Week, "2023-01-09T05:00:00Z"),
+            utc("2023-01-16T05:00:00Z")
+        );
+        // Still Sunday in New York
+        assert_eq!(
+            next(Partition::Week, "2023-01-09T04:59:59Z"),
+            utc("2023-01-09T05:00:00Z")
+        );
+        assert_eq!(
+            next(Partition::Month, "2023-01-31T12:00:00Z"),
+            utc("2023-02-01T05:00:00Z")
+        );
+        assert_eq!(
+            next(Partition::Month, "2023-12-15T00:00:00Z"),
+            utc("2024-01-01T05:00:00Z")
+        );
+        assert_eq!(
+            next(Partition::Month, "2023-03-01T05:00:00Z"),
+            utc("2023-04-01T04:00:00Z")
+        );
+    }
+
+    #[test]
+    fn generates_date_series() {
+        let series = |first: &str, last: &str, step: &str| {
+            date_series(date(first), date(last), &step.parse().unwrap())
+        };
+        assert_eq!(
+            series("2023-01-31", "2023-05-01", "P1M").unwrap(),
+            ["2023-01-31", "2023-02-28", "2023-03-31", "2023-04-30"].map(date)
+        );
+        assert_eq!(
+            series("2023-01-01", "2023-01-15", "P1W").unwrap(),
+            ["2023-01-01", "2023-01-08", "2023-01-15"].map(date)
+        );
+        assert_eq!(
+            series("2023-01-01", "2023-01-01", "1").unwrap(),
+            [date("2023-01-01")]
+        );
+        assert!(series("2023-01-02", "2023-01-01", "1").unwrap().is_empty());
+        assert_eq!(
+            series("2023-01-01", "2023-01-01", "P999999Y")
+                .unwrap()
+                .len(),
+            1
+        );
+        for step in ["-1", "0", "PT1H", "P1DT1H"] {
+            assert!(series("2023-01-01", "2023-02-01", step).is_err(), "{step}");
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_periods() {
+        for s in [
+            "P999999999999Y",
+            "P9999999999999999999D",
+            "PT9223372036854775807H",
+        ] {
+            assert!(s.parse::<Period>().is_err(), "{s}");
+        }
+        let start = date("2023-01-01");
+        for s in ["P999999Y", "P99999999999D", "-P999999Y"] {
+            let period = s.parse().unwrap();
+            assert!(TimeSpan::for_period(start, &period).is_err(), "{s}");
+        }
     }
 }